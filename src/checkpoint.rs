@@ -0,0 +1,315 @@
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use anyhow::format_err;
+use serde::{Deserialize, Serialize};
+
+use crate::client::error::RouterFailure;
+use crate::input::LinePosition;
+use crate::timerange::TimeRange;
+use crate::types::{Config, Dedup, LogContent, LogFormat, RouterResult, RouterTiming, Sampling};
+
+/// One processed log entry. Replaying the journal rebuilds the aggregates exactly, which the
+/// summarized statistics could not.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum JournalEntry {
+    #[serde(rename = "compared")]
+    Compared {
+        #[serde(rename = "index")]
+        index: u64,
+        #[serde(rename = "requestTime")]
+        request_time: Option<chrono::DateTime<chrono::FixedOffset>>,
+        #[serde(rename = "request")]
+        request: LogContent,
+        #[serde(rename = "timing")]
+        timing: RouterTiming,
+        #[serde(rename = "old")]
+        old: RouterResult,
+        #[serde(rename = "new")]
+        new: RouterResult,
+    },
+    #[serde(rename = "failed")]
+    Failed {
+        #[serde(rename = "request")]
+        request: LogContent,
+        #[serde(rename = "failure")]
+        failure: RouterFailure,
+    },
+    /// A line matching the log format that could not be decoded.
+    #[serde(rename = "undecodable")]
+    Undecodable {
+        #[serde(rename = "line")]
+        line: String,
+    },
+}
+
+/// Processed entries of a run, one JSON object per line.
+pub struct Journal {
+    writer: BufWriter<File>,
+}
+
+impl Journal {
+    pub fn path(checkpoint_path: &str) -> String {
+        format!("{}.journal", checkpoint_path)
+    }
+
+    /// Appends to the journal of a resumed run, starts a new one otherwise.
+    pub fn open(path: &str, resume: bool) -> anyhow::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resume)
+            .truncate(!resume)
+            .open(path)?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    pub fn write(&mut self, entry: &JournalEntry) -> anyhow::Result<()> {
+        serde_json::to_writer(&mut self.writer, entry)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn read(path: &str) -> anyhow::Result<impl Iterator<Item = anyhow::Result<JournalEntry>>> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(reader.lines().map(|line| Ok(serde_json::from_str(&line?)?)))
+    }
+}
+
+/// Settings deciding which entries a run compares and how, a run is only resumed with the
+/// same ones so the statistics of two different runs never mix.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    #[serde(rename = "oldUrl")]
+    pub old_url: String,
+    #[serde(rename = "newUrl")]
+    pub new_url: String,
+    #[serde(rename = "useBaseTokens")]
+    pub use_base_tokens: String,
+    #[serde(rename = "simultaneousCalls")]
+    pub simultaneous_calls: bool,
+    #[serde(rename = "dedup")]
+    pub dedup: Dedup,
+    #[serde(rename = "sampling")]
+    pub sampling: Sampling,
+    #[serde(rename = "maxComparedPaths")]
+    pub max_compared_paths: Option<usize>,
+    #[serde(rename = "logFormat")]
+    pub log_format: LogFormat,
+    #[serde(rename = "maxCount")]
+    pub max_count: u64,
+    #[serde(rename = "since")]
+    pub since: Option<String>,
+    #[serde(rename = "until")]
+    pub until: Option<String>,
+}
+
+impl Fingerprint {
+    pub fn of(config: &Config) -> Self {
+        Self {
+            old_url: config.old_url.clone(),
+            new_url: config.new_url.clone(),
+            use_base_tokens: config.use_base_tokens.clone(),
+            simultaneous_calls: config.simultaneous_calls,
+            dedup: config.dedup.clone(),
+            sampling: config.sampling.clone(),
+            max_compared_paths: config.max_compared_paths,
+            log_format: config.log_format.clone(),
+            max_count: config.max_count,
+            since: config.since.clone(),
+            until: config.until.clone(),
+        }
+    }
+
+    /// Config names of the settings that differ from `other`.
+    pub fn differences(&self, other: &Self) -> Vec<String> {
+        let (ours, theirs) = match (serde_json::to_value(self), serde_json::to_value(other)) {
+            (Ok(serde_json::Value::Object(ours)), Ok(serde_json::Value::Object(theirs))) => (ours, theirs),
+            _ => return vec!["settings".to_owned()],
+        };
+        ours.iter().filter(|(name, value)| theirs.get(*name) != Some(value)).map(|(name, _)| name.clone()).collect()
+    }
+}
+
+/// An input of a checkpointed run. Device and inode tell a rotated or replaced file from the
+/// one the run read.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InputFile {
+    #[serde(rename = "path")]
+    pub path: PathBuf,
+    #[serde(rename = "dev")]
+    pub dev: u64,
+    #[serde(rename = "ino")]
+    pub ino: u64,
+    #[serde(rename = "len")]
+    pub len: u64,
+}
+
+impl InputFile {
+    pub fn stat(path: &Path) -> anyhow::Result<Self> {
+        let metadata = std::fs::metadata(path).map_err(|e| format_err!("failed to stat {}: {}", path.display(), e))?;
+        Ok(Self {
+            path: path.to_owned(),
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+            len: metadata.len(),
+        })
+    }
+}
+
+/// Progress of a run, saved every `checkpointEvery` processed entries and before the final
+/// reports are written.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Inputs of the run, a resumed run must read the same files. Their length is the one
+    /// sampled when the run started, or the one at the last save for a streamed run.
+    #[serde(rename = "inputs")]
+    pub inputs: Vec<InputFile>,
+    #[serde(rename = "fingerprint")]
+    pub fingerprint: Fingerprint,
+    /// `since` and `until` as resolved when the run started, relative bounds are not resolved
+    /// again on resume.
+    #[serde(rename = "timeRange")]
+    pub time_range: TimeRange,
+    /// End of the last processed entry; `None` for sampled runs, which sample again with
+    /// the same seed and skip `consumed` entries instead.
+    #[serde(rename = "position")]
+    pub position: Option<LinePosition>,
+    /// Entries compared, failed or undecodable so far.
+    #[serde(rename = "consumed")]
+    pub consumed: u64,
+    /// Index of the next compared entry.
+    #[serde(rename = "index")]
+    pub index: u64,
+    #[serde(rename = "samplingSeed")]
+    pub sampling_seed: Option<u64>,
+    #[serde(rename = "startedAt")]
+    pub started_at: String,
+    /// Length of every append-mode output, the journal included; whatever was written
+    /// after the checkpoint is cut off on resume.
+    #[serde(rename = "outputs")]
+    pub outputs: BTreeMap<String, u64>,
+}
+
+impl Checkpoint {
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let file = File::open(path).map_err(|e| format_err!("failed to open checkpoint {}: {}", path, e))?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    /// Written to a temporary file first so a crash never leaves a torn checkpoint.
+    pub fn save(&self, path: &str) -> anyhow::Result<()> {
+        let tmp = format!("{}.tmp", path);
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Refuses inputs that are not the files the checkpointed run read: other paths, replaced
+    /// or truncated files, and for a sampled run files that grew, which would draw another
+    /// sample.
+    pub fn check_inputs(&self, inputs: &[PathBuf]) -> anyhow::Result<()> {
+        if !self.inputs.iter().map(|input| &input.path).eq(inputs.iter()) {
+            let paths: Vec<&PathBuf> = self.inputs.iter().map(|input| &input.path).collect();
+            return Err(format_err!("the log files differ from the checkpointed run: {:?}", paths));
+        }
+        for input in self.inputs.iter() {
+            let current = InputFile::stat(&input.path)?;
+            let path = input.path.display();
+            if (current.dev, current.ino) != (input.dev, input.ino) {
+                return Err(format_err!("{} is not the file the checkpointed run read, it was rotated or replaced", path));
+            }
+            if current.len < input.len {
+                return Err(format_err!("{} is shorter than at the checkpoint ({} < {} bytes)", path, current.len, input.len));
+            }
+            if self.sampling_seed.is_some() && current.len > input.len {
+                return Err(format_err!("{} grew since the sampled run started, it would draw another sample", path));
+            }
+        }
+        Ok(())
+    }
+
+    /// Current length of each output, missing files count as empty.
+    pub fn measure_outputs<'a>(paths: impl Iterator<Item = &'a String>) -> BTreeMap<String, u64> {
+        paths
+            .map(|path| (path.clone(), std::fs::metadata(path).map_or(0, |metadata| metadata.len())))
+            .collect()
+    }
+
+    pub fn truncate_outputs(&self) -> anyhow::Result<()> {
+        for (path, len) in self.outputs.iter() {
+            let file = OpenOptions::new().create(true).write(true).truncate(false).open(path)?;
+            let current = file.metadata()?.len();
+            if current < *len {
+                return Err(format_err!("{} is shorter than at the checkpoint ({} < {} bytes)", path, current, len));
+            }
+            file.set_len(*len)?;
+        }
+        Ok(())
+    }
+}
+
+/// Journals processed entries and saves the checkpoint of a run.
+pub struct Checkpointer {
+    path: String,
+    every: u64,
+    journal: Journal,
+    outputs: Vec<String>,
+    checkpoint: Checkpoint,
+}
+
+impl Checkpointer {
+    /// `checkpoint` is where the run starts, the loaded one when resuming. `outputs` are the
+    /// append-mode outputs besides the journal.
+    pub fn new(path: &str, every: u64, mut outputs: Vec<String>, checkpoint: Checkpoint, resume: bool) -> anyhow::Result<Self> {
+        let journal_path = Journal::path(path);
+        let journal = Journal::open(&journal_path, resume)?;
+        outputs.push(journal_path);
+        Ok(Self {
+            path: path.to_owned(),
+            every: every.max(1),
+            journal,
+            outputs,
+            checkpoint,
+        })
+    }
+
+    pub fn record(&mut self, entry: &JournalEntry, position: Option<LinePosition>, index: u64) -> anyhow::Result<()> {
+        self.journal.write(entry)?;
+        self.checkpoint.consumed += 1;
+        self.checkpoint.position = position;
+        self.checkpoint.index = index;
+        Ok(())
+    }
+
+    pub fn due(&self) -> bool {
+        self.checkpoint.consumed.is_multiple_of(self.every)
+    }
+
+    /// The other outputs must be flushed first so their lengths are final.
+    pub fn save(&mut self) -> anyhow::Result<()> {
+        self.journal.flush()?;
+        // A streamed run only read up to its position, it may resume in a file that grew
+        // since but not in a rotated one.
+        if self.checkpoint.sampling_seed.is_none() {
+            for input in self.checkpoint.inputs.iter_mut() {
+                match InputFile::stat(&input.path) {
+                    Ok(current) if (current.dev, current.ino) == (input.dev, input.ino) => input.len = current.len,
+                    _ => {}
+                }
+            }
+        }
+        self.checkpoint.outputs = Checkpoint::measure_outputs(self.outputs.iter());
+        self.checkpoint.save(&self.path)
+    }
+}
@@ -1,9 +1,10 @@
 use reqwest::Url;
 use futures::Future;
 use backoff::{future::retry_notify, Error::{Transient, Permanent}};
+use std::cell::Cell;
 use std::time::Duration;
-use anyhow::format_err;
 use super::{LogContent, RouterResult};
+use super::error::RouterError;
 
 
 #[derive(Debug, Clone)]
@@ -29,12 +30,16 @@ impl RouterApiClient {
         }
     }
 
-    async fn with_retries<I, E, Fn, Fut>(&self, operation: Fn) -> anyhow::Result<I>
+    /// Runs `operation` with the exponential backoff and returns its output, or the last error
+    /// once the backoff gives up, together with the number of retries made.
+    async fn with_retries<I, E, Fn, Fut>(&self, operation: Fn) -> (Result<I, E>, u32)
         where
             Fn: FnMut() -> Fut,
             Fut: Future<Output=Result<I, backoff::Error<E>>>,
             E: std::fmt::Display, {
+        let retries = Cell::new(0u32);
         let notify = |err, next_after: Duration| {
+            retries.set(retries.get() + 1);
             let duration_secs = next_after.as_millis() as f32 / 1000.0f32;
             log::warn!(
                "Failed to reach server err: <{}>, retrying after: {:.1}s",
@@ -43,14 +48,12 @@ impl RouterApiClient {
             )
         };
 
-        retry_notify(Self::get_backoff(), operation, notify)
-            .await
-            .map_err(|e| {
-                format_err!(
-                    "Prover can't reach server, for the max elapsed time of the backoff: {}",
-                    e
-                )
-            })
+        let res = retry_notify(Self::get_backoff(), operation, notify).await;
+        let res = res.map_err(|e| {
+            log::warn!("Prover can't reach server, for the max elapsed time of the backoff: {}", e);
+            e
+        });
+        (res, retries.get())
     }
 
     fn get_backoff() -> backoff::ExponentialBackoff {
@@ -64,37 +67,47 @@ impl RouterApiClient {
         }
     }
 
-    pub async fn call_old_router(&mut self, log_content: &LogContent) -> anyhow::Result<RouterResult> {
-        gen_url(&mut self.old_router_url, log_content, None);
-        self.call_router(&self.old_router_url).await
+    /// The result of the call and the retries it took, which failed calls report too.
+    pub async fn call_old_router(&self, log_content: &LogContent) -> (Result<RouterResult, RouterError>, u32) {
+        let url = request_url(&self.old_router_url, log_content, None);
+        self.call_router(&url).await
     }
 
-    pub async fn call_new_router(&mut self, log_content: &LogContent) -> anyhow::Result<RouterResult> {
-        gen_url(&mut self.new_router_url, log_content, Some(self.use_base_tokens.clone()));
-        self.call_router(&self.new_router_url).await
+    pub async fn call_new_router(&self, log_content: &LogContent) -> (Result<RouterResult, RouterError>, u32) {
+        let url = request_url(&self.new_router_url, log_content, Some(self.use_base_tokens.clone()));
+        self.call_router(&url).await
     }
 
-    async fn call_router(&self, url: &Url) -> anyhow::Result<RouterResult> {
-        println!("{}", url.to_string());
+    /// Calls one router, a response is only `Ok` when it carries code 0 and at least one path.
+    async fn call_router(&self, url: &Url) -> (Result<RouterResult, RouterError>, u32) {
+        println!("{}", url);
         let operation = || async {
             let response = self
                 .http_client
                 .get(url.clone())
                 .send()
                 .await
-                .map_err(|e| format_err!("failed to send call router request: {}", e))?;
+                .map_err(RouterError::from_reqwest)?;
             if response.status() != reqwest::StatusCode::OK {
-                return Err(Transient(format_err!("router request error:{:?}", response.status())));
+                return Err(Transient(RouterError::HttpStatus(response.status().as_u16())));
             }
             response
-                .json()
+                .json::<RouterResult>()
                 .await
-                .map_err(|e| Permanent(format_err!("failed parse json on RouterResult request: {}", e)))
+                .map_err(|e| Permanent(RouterError::from_reqwest(e)))
         };
-        self.with_retries(operation).await
+        let (result, retries) = self.with_retries(operation).await;
+        (result.and_then(RouterError::check), retries)
     }
 }
 
+/// The URL requested from the router at `base` for `log_content`.
+pub(crate) fn request_url(base: &Url, log_content: &LogContent, use_base_tokens_op: Option<String>) -> Url {
+    let mut url = base.clone();
+    gen_url(&mut url, log_content, use_base_tokens_op);
+    url
+}
+
 fn gen_url(url: &mut Url, log_content: &LogContent, use_base_tokens_op: Option<String>) {
     let mut res = format!(
         "fromToken={}&fromTokenAddr={}&toToken={}&toTokenAddr={}&inAmount={}&fromDecimal={}&toDecimal={}",
@@ -106,8 +119,10 @@ fn gen_url(url: &mut Url, log_content: &LogContent, use_base_tokens_op: Option<S
         log_content.from_decimal,
         log_content.to_decimal
     );
-    if use_base_tokens_op.is_some() {
-        res = format!("{}&useBaseTokens={}", res, use_base_tokens_op.unwrap())
+    // A logged call keeps its own useBaseTokens when replayed.
+    let use_base_tokens_op = use_base_tokens_op.map(|configured| log_content.use_base_tokens.clone().unwrap_or(configured));
+    if let Some(use_base_tokens) = use_base_tokens_op {
+        res = format!("{}&useBaseTokens={}", res, use_base_tokens)
     }
     url.set_query(Some(res.as_str()))
 }
\ No newline at end of file
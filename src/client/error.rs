@@ -0,0 +1,112 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+use crate::types::{RouterResult, RouterTiming};
+
+/// Why a call to a router endpoint did not produce comparable paths.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RouterError {
+    /// The request could not be sent or the connection broke.
+    Transport(String),
+    /// The request did not complete within the client timeout.
+    Timeout,
+    /// The router answered with a non-200 HTTP status.
+    HttpStatus(u16),
+    /// The body was not a valid `RouterResult`.
+    JsonDecode(String),
+    /// The router answered with a non-zero business `code`.
+    Business { code: u16, message: String },
+    /// The router answered successfully but without any path.
+    EmptyData,
+}
+
+impl RouterError {
+    pub const KINDS: [&'static str; 6] = ["transport", "timeout", "httpStatus", "jsonDecode", "business", "emptyData"];
+
+    /// Stable class name used to aggregate errors in the report.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RouterError::Transport(_) => "transport",
+            RouterError::Timeout => "timeout",
+            RouterError::HttpStatus(_) => "httpStatus",
+            RouterError::JsonDecode(_) => "jsonDecode",
+            RouterError::Business { .. } => "business",
+            RouterError::EmptyData => "emptyData",
+        }
+    }
+
+    /// Passes on a decoded response only when it carries code 0 and at least one path.
+    pub(crate) fn check(result: RouterResult) -> Result<RouterResult, Self> {
+        if result.code != 0 {
+            return Err(RouterError::Business { code: result.code, message: result.message });
+        }
+        if result.data.as_ref().is_none_or(|paths| paths.is_empty()) {
+            return Err(RouterError::EmptyData);
+        }
+        Ok(result)
+    }
+
+    pub(crate) fn from_reqwest(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            RouterError::Timeout
+        } else if e.is_decode() {
+            RouterError::JsonDecode(e.to_string())
+        } else {
+            RouterError::Transport(e.to_string())
+        }
+    }
+}
+
+impl fmt::Display for RouterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouterError::Transport(e) => write!(f, "failed to send call router request: {}", e),
+            RouterError::Timeout => write!(f, "router request timed out"),
+            RouterError::HttpStatus(status) => write!(f, "router request error: status {}", status),
+            RouterError::JsonDecode(e) => write!(f, "failed parse json on RouterResult request: {}", e),
+            RouterError::Business { code, message } => write!(f, "router returned code {}: {}", code, message),
+            RouterError::EmptyData => write!(f, "router returned no path"),
+        }
+    }
+}
+
+impl std::error::Error for RouterError {}
+
+/// Errors of the old and new calls made for one log entry, `None` for a side that succeeded,
+/// and the timing of both calls.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RouterFailure {
+    pub old: Option<RouterError>,
+    pub new: Option<RouterError>,
+    #[serde(default)]
+    pub timing: RouterTiming,
+}
+
+impl fmt::Display for RouterFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let describe = |e: &Option<RouterError>| e.as_ref().map_or("ok".to_owned(), |e| e.to_string());
+        write!(f, "old: {}, new: {}", describe(&self.old), describe(&self.new))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(code: u16, data: serde_json::Value) -> RouterResult {
+        serde_json::from_value(serde_json::json!({ "code": code, "message": "no route", "data": data })).unwrap()
+    }
+
+    #[test]
+    fn check_classifies_unusable_responses() {
+        let path = serde_json::json!([{ "amount": "1" }]);
+        assert_eq!(
+            RouterError::check(result(1001, path.clone())).unwrap_err(),
+            RouterError::Business { code: 1001, message: "no route".to_owned() }
+        );
+        assert_eq!(RouterError::check(result(0, serde_json::Value::Null)).unwrap_err(), RouterError::EmptyData);
+        assert_eq!(RouterError::check(result(0, serde_json::json!([]))).unwrap_err(), RouterError::EmptyData);
+        assert_eq!(RouterError::check(result(0, path)).unwrap().data.unwrap().len(), 1);
+        assert_eq!(RouterError::EmptyData.kind(), "emptyData");
+    }
+}
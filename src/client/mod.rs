@@ -1,5 +1,7 @@
-pub use super::types::{LogContent, RouterResult, Path};
+pub use super::types::{LogContent, RouterResult};
 
+#[allow(clippy::module_inception)]
 pub mod client;
+pub mod error;
 
 
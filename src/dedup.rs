@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+
+use crate::types::{Dedup, LogContent};
+
+/// Tracks the keys seen so far and decides which log entries are compared.
+pub struct Deduper {
+    policy: Dedup,
+    seen: HashMap<String, usize>,
+}
+
+impl Deduper {
+    pub fn new(policy: Dedup) -> Self {
+        Self {
+            policy,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Returns whether `log_content` should be compared, and counts it when it is.
+    pub fn admit(&mut self, log_content: &LogContent) -> bool {
+        let pair = || format!("{}_{}", log_content.from_token, log_content.to_token);
+        let (key, limit) = match &self.policy {
+            Dedup::None => return true,
+            Dedup::Pair => (pair(), 1),
+            Dedup::PairAmountBucket => (format!("{}_{:?}", pair(), amount_bucket(log_content)), 1),
+            Dedup::Request => (serde_json::to_string(log_content).unwrap_or_default(), 1),
+            Dedup::PairSamples { per_pair } => (pair(), *per_pair),
+        };
+        let count = self.seen.entry(key).or_default();
+        if *count >= limit {
+            return false;
+        }
+        *count += 1;
+        true
+    }
+}
+
+/// Order of magnitude of `in_amount` in whole tokens.
+fn amount_bucket(log_content: &LogContent) -> Option<i32> {
+    log_content.amount().map(|amount| amount.log10().floor() as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn log_content(in_amount: &str) -> LogContent {
+        test_support::request("TRX", "USDT", in_amount)
+    }
+
+    #[test]
+    fn amount_bucket_separates_trade_sizes() {
+        let mut deduper = Deduper::new(Dedup::PairAmountBucket);
+        assert!(deduper.admit(&log_content("5000000")));
+        assert!(!deduper.admit(&log_content("9000000")));
+        assert!(deduper.admit(&log_content("50000000000")));
+        assert!(deduper.admit(&log_content("0")));
+        assert!(!deduper.admit(&log_content("abc")));
+
+        let mut deduper = Deduper::new(Dedup::PairSamples { per_pair: 2 });
+        assert!(deduper.admit(&log_content("1")));
+        assert!(deduper.admit(&log_content("1")));
+        assert!(!deduper.admit(&log_content("2")));
+    }
+}
@@ -0,0 +1,396 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use anyhow::format_err;
+use flate2::read::MultiGzDecoder;
+use futures::channel::mpsc;
+use futures::SinkExt;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::types::{Config, LogContent};
+
+/// Input name standing for stdin.
+pub const STDIN: &str = "-";
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Input logs of `config`, globs expanded, ordered so rotated files are read chronologically:
+/// highest rotation number first (`tron.log.2.gz`, `tron.log.1.gz`, `tron.log`), then oldest
+/// modification time first. `-` reads stdin and cannot be combined with other inputs.
+pub fn resolve_inputs(config: &Config) -> anyhow::Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    let patterns: Vec<&String> = std::iter::once(&config.log_file_path)
+        .chain(config.log_file_paths.iter())
+        .filter(|pattern| !pattern.is_empty())
+        .collect();
+    if patterns.iter().any(|pattern| *pattern == STDIN) {
+        if patterns.len() > 1 {
+            return Err(format_err!("stdin (\"-\") cannot be combined with other log files"));
+        }
+        return Ok(vec![PathBuf::from(STDIN)]);
+    }
+    for pattern in patterns {
+        let matched: Vec<PathBuf> = glob::glob(pattern)?.collect::<Result<_, _>>()?;
+        if matched.is_empty() {
+            return Err(format_err!("no log file matches {}", pattern));
+        }
+        paths.extend(matched);
+    }
+    if paths.is_empty() {
+        return Err(format_err!("neither logFilePath nor logFilePaths is set"));
+    }
+    paths.sort();
+    paths.dedup();
+    let mut dated = paths
+        .into_iter()
+        .map(|path| Ok((std::cmp::Reverse(rotation_number(&path)), std::fs::metadata(&path)?.modified()?, path)))
+        .collect::<io::Result<Vec<_>>>()?;
+    dated.sort();
+    Ok(dated.into_iter().map(|(_, _, path)| path).collect())
+}
+
+/// Number logrotate appended to a rotated file, `tron.log.2.gz` is 2; 0 for the live file and
+/// names without one.
+fn rotation_number(path: &Path) -> u64 {
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    let name = name.trim_end_matches(".gz").trim_end_matches(".zst");
+    name.rsplit_once('.').and_then(|(_, suffix)| suffix.parse().ok()).unwrap_or(0)
+}
+
+/// A decoded routing request with when and where it was logged.
+#[derive(Clone, Debug)]
+pub struct LogEntry {
+    pub content: LogContent,
+    pub time: Option<chrono::DateTime<chrono::FixedOffset>>,
+    /// `None` when the entry cannot be found again, e.g. when following a log.
+    pub position: Option<LinePosition>,
+}
+
+/// Where a line ends: index of the input and offset just past the line, counted in
+/// decompressed bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinePosition {
+    #[serde(rename = "input")]
+    pub input: usize,
+    #[serde(rename = "offset")]
+    pub offset: u64,
+}
+
+/// Opens `path`, or stdin for `-`, decompressing gzip and zstd content detected by its
+/// magic bytes, and skips its first `offset` decompressed bytes.
+pub fn open_log(path: &Path, offset: u64) -> io::Result<Box<dyn BufRead>> {
+    if path == Path::new(STDIN) {
+        if offset > 0 {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "stdin cannot be read from an offset"));
+        }
+        return decompress(BufReader::new(io::stdin()));
+    }
+    let mut reader = BufReader::new(File::open(path)?);
+    if !is_compressed(reader.fill_buf()?) {
+        reader.seek(SeekFrom::Start(offset))?;
+        return Ok(Box::new(reader));
+    }
+    let mut reader = decompress(reader)?;
+    io::copy(&mut reader.by_ref().take(offset), &mut io::sink())?;
+    Ok(reader)
+}
+
+fn is_compressed(head: &[u8]) -> bool {
+    head.starts_with(&GZIP_MAGIC) || head.starts_with(&ZSTD_MAGIC)
+}
+
+fn decompress<R: BufRead + 'static>(mut reader: R) -> io::Result<Box<dyn BufRead>> {
+    let head = reader.fill_buf()?;
+    let decoded: Box<dyn Read> = if head.starts_with(&GZIP_MAGIC) {
+        Box::new(MultiGzDecoder::new(reader))
+    } else if head.starts_with(&ZSTD_MAGIC) {
+        Box::new(zstd::Decoder::with_buffer(reader)?)
+    } else {
+        return Ok(Box::new(reader));
+    };
+    Ok(Box::new(BufReader::new(decoded)))
+}
+
+/// Lines of every input in order with the position they end at, starting at `start` when
+/// given; files are opened lazily, one at a time.
+pub fn log_lines(
+    paths: Vec<PathBuf>,
+    start: Option<LinePosition>,
+) -> impl Iterator<Item = io::Result<(LinePosition, String)>> {
+    let start = start.unwrap_or(LinePosition { input: 0, offset: 0 });
+    paths.into_iter().enumerate().skip(start.input).flat_map(move |(input, path)| {
+        let offset = if input == start.input { start.offset } else { 0 };
+        info!("Reading {} from offset {}", path.display(), offset);
+        let lines: Box<dyn Iterator<Item = io::Result<(LinePosition, String)>>> = match open_log(&path, offset) {
+            Ok(reader) => Box::new(PositionedLines {
+                reader,
+                position: LinePosition { input, offset },
+            }),
+            Err(e) => Box::new(std::iter::once(Err(e))),
+        };
+        lines
+    })
+}
+
+/// Like `BufRead::lines`, also yielding the position each line ends at.
+struct PositionedLines {
+    reader: Box<dyn BufRead>,
+    position: LinePosition,
+}
+
+impl Iterator for PositionedLines {
+    type Item = io::Result<(LinePosition, String)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = vec![];
+        match self.reader.read_until(b'\n', &mut line) {
+            Ok(0) => None,
+            Ok(read) => {
+                self.position.offset += read as u64;
+                Some(Ok((self.position, line_text(line))))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// `line` without its line ending. Invalid UTF-8 is replaced instead of failing the read, so
+/// a garbled line is skipped or counted as undecodable like any other bad line.
+fn line_text(mut line: Vec<u8>) -> String {
+    while line.last().is_some_and(|byte| matches!(byte, b'\r' | b'\n')) {
+        line.pop();
+    }
+    String::from_utf8(line).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+}
+
+/// Lines buffered between a reading thread and the comparison loop.
+const LINE_BUFFER: usize = 1024;
+
+/// `log_lines` read on a dedicated thread, so file reads, decompression and waiting on a
+/// live pipe such as `kubectl logs -f` never hold back the comparisons in flight, whose
+/// latency is timed on the comparison task. The thread stops at the end of the inputs, on
+/// the first read error or once the receiver is dropped.
+pub fn read_lines(paths: Vec<PathBuf>, start: Option<LinePosition>) -> mpsc::Receiver<io::Result<(LinePosition, String)>> {
+    let (mut sender, receiver) = mpsc::channel(LINE_BUFFER);
+    std::thread::spawn(move || {
+        for line in log_lines(paths, start) {
+            let failed = line.is_err();
+            if futures::executor::block_on(sender.send(line)).is_err() || failed {
+                return;
+            }
+        }
+    });
+    receiver
+}
+
+/// Tails `path` on a dedicated thread like `tail -F`: waits for appended lines, reopens the
+/// path when the file is replaced by rotation and rereads it from the start when it is
+/// truncated. The thread stops once the receiver is dropped.
+pub fn follow_lines(path: PathBuf, poll: Duration, from_start: bool) -> mpsc::Receiver<io::Result<String>> {
+    let (mut sender, receiver) = mpsc::channel(LINE_BUFFER);
+    std::thread::spawn(move || {
+        let mut send = |line: io::Result<String>| futures::executor::block_on(sender.send(line)).is_ok();
+        let mut tail = match Tail::open(&path, from_start) {
+            Ok(tail) => tail,
+            Err(e) => {
+                send(Err(e));
+                return;
+            }
+        };
+        info!("Following {}", path.display());
+        loop {
+            let line = match tail.next_line() {
+                Ok(Some(line)) => Ok(line),
+                Ok(None) => {
+                    std::thread::sleep(poll);
+                    match tail.check_replaced(&path) {
+                        Ok(Some(line)) => Ok(line),
+                        Ok(None) => continue,
+                        Err(e) => Err(e),
+                    }
+                }
+                Err(e) => Err(e),
+            };
+            if !send(line) {
+                return;
+            }
+        }
+    });
+    receiver
+}
+
+/// The followed file, its identity and the current read position.
+struct Tail {
+    reader: BufReader<File>,
+    id: (u64, u64),
+    position: u64,
+    partial: Vec<u8>,
+}
+
+impl Tail {
+    fn open(path: &Path, from_start: bool) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        let mut reader = BufReader::new(file);
+        let position = if from_start { 0 } else { reader.seek(SeekFrom::End(0))? };
+        Ok(Self {
+            reader,
+            id: (metadata.dev(), metadata.ino()),
+            position,
+            partial: vec![],
+        })
+    }
+
+    /// Next complete line, `None` at the end of the file; a trailing line without newline
+    /// is kept until the rest is written.
+    fn next_line(&mut self) -> io::Result<Option<String>> {
+        let read = self.reader.read_until(b'\n', &mut self.partial)?;
+        self.position += read as u64;
+        if read == 0 || !self.partial.ends_with(b"\n") {
+            return Ok(None);
+        }
+        Ok(Some(line_text(std::mem::take(&mut self.partial))))
+    }
+
+    /// Switches to the new file after a rotation, or rewinds after a truncation. A missing
+    /// path is assumed to be mid-rotation and checked again on the next poll. The old file
+    /// is read to its end before switching, its last line is returned if it has no newline.
+    fn check_replaced(&mut self, path: &Path) -> io::Result<Option<String>> {
+        let metadata = match std::fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        if (metadata.dev(), metadata.ino()) != self.id {
+            // Lines written right before the rename are read first, the next poll switches.
+            if !self.reader.fill_buf()?.is_empty() {
+                return Ok(None);
+            }
+            warn!("{} was rotated, reopening it", path.display());
+            let rest = std::mem::take(&mut self.partial);
+            *self = Self::open(path, true)?;
+            return Ok((!rest.is_empty()).then(|| line_text(rest)));
+        }
+        if metadata.len() < self.position {
+            warn!("{} was truncated, reading it from the start", path.display());
+            self.position = self.reader.seek(SeekFrom::Start(0))?;
+            self.partial.clear();
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use crate::test_support;
+
+    #[test]
+    fn reads_plain_gzip_and_zstd_logs() {
+        let dir = std::env::temp_dir().join(format!("parse_logs_input_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let plain = dir.join("tron.log");
+        std::fs::write(&plain, b"c\r\n\xffd\n").unwrap();
+        let gzip = dir.join("tron.log.1.gz");
+        let mut encoder = flate2::write::GzEncoder::new(File::create(&gzip).unwrap(), flate2::Compression::default());
+        encoder.write_all(b"a\nb\n").unwrap();
+        encoder.finish().unwrap();
+        let zstd = dir.join("tron.log.2.zst");
+        std::fs::write(&zstd, zstd::encode_all(&b"z\n"[..], 0).unwrap()).unwrap();
+
+        let inputs = vec![zstd, gzip, plain];
+        let lines: Vec<(LinePosition, String)> = log_lines(inputs.clone(), None).collect::<io::Result<_>>().unwrap();
+        let texts: Vec<&str> = lines.iter().map(|(_, line)| line.as_str()).collect();
+        assert_eq!(texts, ["z", "a", "b", "c", "\u{fffd}d"]);
+
+        let resumed: Vec<String> = log_lines(inputs, Some(lines[1].0))
+            .map(|line| line.map(|(_, line)| line))
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(resumed, ["b", "c", "\u{fffd}d"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tail_handles_partial_lines_rotation_and_truncation() {
+        let dir = std::env::temp_dir().join(format!("parse_logs_tail_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tron.log");
+        let append = |text: &str| {
+            let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&path).unwrap();
+            file.write_all(text.as_bytes()).unwrap();
+        };
+        append("before\n");
+        let mut tail = Tail::open(&path, false).unwrap();
+        assert_eq!(tail.next_line().unwrap(), None);
+
+        // A line is only yielded once its newline is written.
+        append("a\nb");
+        assert_eq!(tail.next_line().unwrap().as_deref(), Some("a"));
+        assert_eq!(tail.next_line().unwrap(), None);
+        append("c\n");
+        assert_eq!(tail.next_line().unwrap().as_deref(), Some("bc"));
+        assert_eq!(tail.check_replaced(&path).unwrap(), None);
+        assert_eq!(tail.next_line().unwrap(), None);
+
+        // Rotation: nothing to do while the path is missing, then the old file is read to its
+        // end, unterminated last line included, before the new one is read from its start.
+        append("x\ny");
+        std::fs::rename(&path, dir.join("tron.log.1")).unwrap();
+        assert_eq!(tail.check_replaced(&path).unwrap(), None);
+        append("d\ne\n");
+        assert_eq!(tail.check_replaced(&path).unwrap(), None);
+        assert_eq!(tail.next_line().unwrap().as_deref(), Some("x"));
+        assert_eq!(tail.next_line().unwrap(), None);
+        assert_eq!(tail.check_replaced(&path).unwrap().as_deref(), Some("y"));
+        assert_eq!(tail.next_line().unwrap().as_deref(), Some("d"));
+        assert_eq!(tail.next_line().unwrap().as_deref(), Some("e"));
+
+        // Truncation: the file is read again from its start.
+        std::fs::OpenOptions::new().write(true).open(&path).unwrap().set_len(0).unwrap();
+        append("f\n");
+        assert_eq!(tail.check_replaced(&path).unwrap(), None);
+        assert_eq!(tail.next_line().unwrap().as_deref(), Some("f"));
+        assert_eq!(tail.next_line().unwrap(), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rotated_inputs_are_ordered_oldest_first() {
+        let dir = std::env::temp_dir().join(format!("parse_logs_rotation_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // Extracted from an archive: every file has the same modification time.
+        let modified = std::time::SystemTime::UNIX_EPOCH + Duration::from_secs(1_790_000_000);
+        for name in ["tron.log", "tron.log.1.gz", "tron.log.2.gz", "tron.log.10.gz", "other.log"] {
+            File::create(dir.join(name)).unwrap().set_modified(modified).unwrap();
+        }
+        // Without rotation numbers the modification time decides.
+        File::create(dir.join("other.log")).unwrap().set_modified(modified - Duration::from_secs(60)).unwrap();
+
+        let config = test_support::config(serde_json::json!({
+            "logFilePaths": [dir.join("tron.log*"), dir.join("other.log")],
+        }));
+        let names: Vec<String> = resolve_inputs(&config)
+            .unwrap()
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, ["tron.log.10.gz", "tron.log.2.gz", "tron.log.1.gz", "other.log", "tron.log"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stdin_is_only_read_alone() {
+        let stdin = test_support::config(serde_json::json!({ "logFilePath": "-" }));
+        assert_eq!(resolve_inputs(&stdin).unwrap(), [PathBuf::from(STDIN)]);
+        let config = test_support::config(serde_json::json!({ "logFilePath": "-", "logFilePaths": ["/var/log/tron.log"] }));
+        assert!(resolve_inputs(&config).unwrap_err().to_string().contains("cannot be combined"));
+        let config = test_support::config(serde_json::json!({ "logFilePaths": ["/var/log/tron.log", "-"] }));
+        assert!(resolve_inputs(&config).is_err());
+    }
+}
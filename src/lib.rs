@@ -1,190 +1,623 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fs::{File, OpenOptions};
-use std::io::{BufRead, BufReader, Write};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Duration;
 use anyhow::format_err;
+use futures::stream::LocalBoxStream;
+use futures::{future, stream, Future, Stream, StreamExt};
 use log::{info, warn};
-use serde_json::Value;
 
 mod utils;
 mod client;
 mod types;
+mod stats;
+mod report;
+mod dedup;
+mod sampling;
+mod input;
+mod checkpoint;
+mod timerange;
+mod parser;
+#[cfg(test)]
+mod test_support;
 
 pub use client::client::RouterApiClient;
-pub use types::{Config, LogContent, RouterResult, CompareResult};
+pub use types::{Config, LogContent, RouterResult, RouterTiming, CompareResult, DecimalDiff, FieldOutcome, FieldOutcomes, Side, Metric, DiffBuckets, RequestCompare, Thresholds, Dedup, Sampling, AmountQuota, Follow, LogFormat};
 pub use utils::init_log;
+pub use client::error::{RouterError, RouterFailure};
+pub use stats::{
+    DiffDistributions, DiffHistograms, ErrorStats, Histogram, LatencyStats, PairStats, PathEquality, PathStats,
+    Regression, RunStats, Summary, TopRegressions, WinLoss,
+};
+pub use report::{check_thresholds, Check, RunMetadata};
+use dedup::Deduper;
+use sampling::sample;
+use input::{follow_lines, log_lines, read_lines, resolve_inputs, LinePosition, LogEntry, STDIN};
+use checkpoint::{Checkpoint, Checkpointer, Fingerprint, InputFile, Journal, JournalEntry};
+use timerange::TimeRange;
+use parser::{log_parser, LogParser};
+use report::csv::CsvWriter;
+use report::html::render_html;
+use report::markdown::render_markdown;
+use report::regressions::render_regressions;
+use report::json::{write_json_report, JsonLinesWriter};
 
-const SWAP_ROUTING_FLAG: &str = "request-swap-routingInV2";
-const GRAFANA_INFO_FLAG: &str = "--GRAFANA_INFO--";
-const LOG_CONTENT_FLAG: &str = "logContent";
 
 
-pub async fn parse_logs_fn(client: &mut RouterApiClient, config: Config) -> anyhow::Result<()> {
-    let file = File::open(config.log_file_path.as_str())?;
-    let reader = BufReader::new(file);
+pub async fn parse_logs_fn(client: &RouterApiClient, config: Config) -> anyhow::Result<()> {
+    let simultaneous = config.simultaneous_calls;
+    compare_logs(config, |log_content| async move { call_router_servers(client, &log_content, simultaneous).await }).await?;
+    Ok(())
+}
+
+/// Compares the log entries selected by `config`, calling both routers through `call`, and
+/// writes the reports; the final statistics are returned.
+async fn compare_logs<F, Fut>(config: Config, call: F) -> anyhow::Result<RunStats>
+where
+    F: Fn(LogContent) -> Fut,
+    Fut: Future<Output = Result<(RouterResult, RouterResult, RouterTiming), RouterFailure>>,
+{
+    let mut started_at = chrono::Utc::now();
+    let inputs = match config.follow {
+        Some(_) => vec![],
+        None => resolve_inputs(&config)?,
+    };
+    let resumed = match (config.checkpoint_path.as_ref(), config.resume) {
+        (None, true) => return Err(format_err!("resume needs checkpointPath")),
+        (Some(_), _) if config.follow.is_some() || inputs == [PathBuf::from(STDIN)] => {
+            return Err(format_err!("checkpoints need log files, follow mode and stdin cannot be resumed"));
+        }
+        (Some(path), true) => {
+            let checkpoint = Checkpoint::load(path)?;
+            checkpoint.check_inputs(&inputs)?;
+            let differences = checkpoint.fingerprint.differences(&Fingerprint::of(&config));
+            if !differences.is_empty() {
+                return Err(format_err!(
+                    "{} differ from the checkpointed run, start over without resuming to change them",
+                    differences.join(", ")
+                ));
+            }
+            // Drops whatever was written after the checkpoint before any output is reopened.
+            checkpoint.truncate_outputs()?;
+            Some(checkpoint)
+        }
+        _ => None,
+    };
+    let time_range = match resumed.as_ref() {
+        Some(checkpoint) => checkpoint.time_range.clone(),
+        None => {
+            let now = chrono::Local::now();
+            TimeRange::new(config.since.as_deref(), config.until.as_deref(), now.with_timezone(now.offset()))?
+        }
+    };
+    let parser = log_parser(&config.log_format);
+    let parser = parser.as_ref();
+    let sampling_seed = match config.sampling {
+        Sampling::First => None,
+        _ => Some(
+            resumed
+                .as_ref()
+                .and_then(|checkpoint| checkpoint.sampling_seed)
+                .or(config.sampling.seed())
+                .unwrap_or_else(rand::random),
+        ),
+    };
+
     let (mut compare_detail_file, mut compare_file) = get_output_files(&config);
+    let mut json_detail = match config.json_detail_path.as_ref() {
+        Some(path) => Some(JsonLinesWriter::open(path)?),
+        None => None,
+    };
+    let mut csv = match config.csv_path.as_ref() {
+        Some(path) => Some(CsvWriter::open(path)?),
+        None => None,
+    };
+    let mut results: Vec<RequestCompare> = vec![];
     let mut index: u64 = 0;
-    let mut diff_amount_pers = init_diff_pers();
-    let mut diff_fee_pers = init_diff_pers();
-    let mut diff_impact_pers = init_diff_pers();
-    let mut diff_inusd_pers = init_diff_pers();
-    let mut diff_ount_pers = init_diff_pers();
-    let mut sum_diff_amount_pers = init_diff_pers();
-    let mut sum_diff_fee_pers = init_diff_pers();
-    let mut sum_diff_impact_pers = init_diff_pers();
-    let mut sum_diff_inusd_pers = init_diff_pers();
-    let mut sum_diff_ount_pers = init_diff_pers();
-    let mut count_paths: Vec<Vec<f64>> = vec![];
-    count_paths.push(vec![0.0; 2]);
-    count_paths.push(vec![0.0; 2]);
-    count_paths.push(vec![0.0; 2]);
-
-    let mut token_pair_maps = HashMap::new();
-
-    let _ = compare_detail_file.write_all("-------------------------Detail-----------------------\n".as_bytes());
-    for line in reader.lines() {
-        if index >= config.max_count {
-            break;
+    let mut stats = RunStats::new(&config);
+    let mut deduper = Deduper::new(config.dedup.clone());
+
+    if let Some(checkpoint) = resumed.as_ref() {
+        started_at = chrono::DateTime::parse_from_rfc3339(&checkpoint.started_at)?.with_timezone(&chrono::Utc);
+        // Sampled runs dedup the whole log again before sampling, only a streamed run
+        // continues with the dedup state of the entries processed so far.
+        let streamed = matches!(config.sampling, Sampling::First);
+        let journal_path = Journal::path(config.checkpoint_path.as_deref().unwrap_or_default());
+        for entry in Journal::read(&journal_path)? {
+            match entry? {
+                JournalEntry::Compared { index, request_time, request, timing, old, new } => {
+                    let compares = CompareResult::gen_from_results(&old, &new, config.max_compared_paths);
+                    let request_compare = RequestCompare { index, request_time, request, timing, compares };
+                    stats.record_success(&request_compare, &old, &new);
+                    if streamed {
+                        deduper.admit(&request_compare.request);
+                    }
+                    if config.json_report_path.is_some() {
+                        results.push(request_compare);
+                    }
+                }
+                JournalEntry::Failed { request, failure } => {
+                    stats.record_failure(&failure, &request);
+                    if streamed {
+                        deduper.admit(&request);
+                    }
+                }
+                JournalEntry::Undecodable { .. } => stats.record_undecodable(),
+            }
         }
-        let line_content = line?;
-        if !line_content.contains(SWAP_ROUTING_FLAG) {
-            continue;
+        index = checkpoint.index;
+        info!("Resuming after {} processed entries, next index {}", checkpoint.consumed, index);
+    }
+    let mut checkpointer = match config.checkpoint_path.as_ref() {
+        Some(path) => {
+            let mut outputs = vec![config.compare_res_detail_path.clone(), config.compare_res_path.clone()];
+            outputs.extend(config.json_detail_path.clone());
+            outputs.extend(config.csv_path.clone());
+            let checkpoint = match resumed.clone() {
+                Some(checkpoint) => checkpoint,
+                None => Checkpoint {
+                    inputs: inputs.iter().map(|path| InputFile::stat(path)).collect::<anyhow::Result<_>>()?,
+                    fingerprint: Fingerprint::of(&config),
+                    time_range: time_range.clone(),
+                    position: None,
+                    consumed: 0,
+                    index: 0,
+                    sampling_seed,
+                    started_at: started_at.to_rfc3339(),
+                    outputs: BTreeMap::new(),
+                },
+            };
+            Some(Checkpointer::new(path, config.checkpoint_every, outputs, checkpoint, resumed.is_some())?)
         }
-        let log_content = decode_to_log_content(&line_content)?;
+        None => None,
+    };
 
-        let key = format!("{}_{}", log_content.from_token, log_content.to_token);
-        if token_pair_maps.contains_key(&key) {
-            continue;
-        } else {
-            token_pair_maps.insert(key.clone(), key);
+    // Streamed logs are read on a thread, see `read_lines`; a sample is drawn before any
+    // router is called.
+    let start = resumed.as_ref().and_then(|checkpoint| checkpoint.position);
+    let entries = match (config.follow.as_ref(), sampling_seed) {
+        (Some(follow), _) => {
+            if !matches!(config.sampling, Sampling::First) {
+                return Err(format_err!("follow mode only supports the first sampling mode"));
+            }
+            if config.log_file_path.is_empty() || config.log_file_path == STDIN {
+                return Err(format_err!("follow mode needs logFilePath to be a file, pipes are read to their end without it"));
+            }
+            let poll = Duration::from_millis(follow.poll_millis);
+            let lines = follow_lines(PathBuf::from(&config.log_file_path), poll, follow.from_start);
+            decode_stream(lines.map(|line| line.map(|line| (None, line))), parser, time_range, deduper)
         }
+        (None, None) => {
+            let lines = read_lines(inputs, start).map(|line| line.map(|(position, line)| (Some(position), line)));
+            decode_stream(lines, parser, time_range, deduper)
+        }
+        // Every mode but `first` needs the whole log before the sample is known, a resumed
+        // run draws the same sample and skips the entries already processed.
+        (None, Some(seed)) => {
+            // Undecodable lines are counted on every pass over the log, they are not sampled
+            // nor journaled.
+            let mut undecodable = 0;
+            let entries = log_lines(inputs, start).filter_map(|line| {
+                let line = match line {
+                    Ok((position, line)) => decode_line(parser, &line, Some(position), &time_range, &mut deduper)?,
+                    Err(e) => return Some(Err(e.into())),
+                };
+                match line {
+                    Ok(entry) => Some(Ok(entry)),
+                    Err(Undecodable { line, error, .. }) => {
+                        warn!("Fail to decode {}: {}", line, error);
+                        undecodable += 1;
+                        None
+                    }
+                }
+            });
+            let sampled = sample(entries, &config.sampling, config.max_count, seed)?;
+            stats.undecodable += undecodable;
+            let consumed = resumed.as_ref().map_or(0, |checkpoint| checkpoint.consumed) as usize;
+            // Sampled entries are not consumed in log order, resuming skips them instead.
+            let sampled = sampled.into_iter().skip(consumed).map(|entry| Ok(Ok(LogEntry { position: None, ..entry })));
+            stream::iter(sampled).boxed_local()
+        }
+    };
 
-        if let Ok((old_res, new_res, _)) = call_router_servers(client, &log_content).await {
-            let res = compare_results(
-                index,
-                serde_json::to_string(&log_content).unwrap(),
-                &old_res,
-                &new_res,
-                &mut compare_detail_file,
-            );
+    // Requests are issued up to `max_concurrency` at a time, but `buffered` yields the
+    // responses in log order, so indexes and the detail file stay deterministic.
+    let mut responses = entries
+        .map(|entry: anyhow::Result<Result<LogEntry, Undecodable>>| async {
+            match entry? {
+                Ok(entry) => {
+                    let res = call(entry.content.clone()).await;
+                    Ok::<_, anyhow::Error>(Ok((entry, res)))
+                }
+                Err(undecodable) => Ok(Err(undecodable)),
+            }
+        })
+        .buffered(config.max_concurrency.max(1));
+
+    // Only follow mode flushes periodically and stops on Ctrl-C, a finite log just runs to its end.
+    let following = config.follow.is_some();
+    let flush_period = Duration::from_secs(config.follow.as_ref().map_or(1, |follow| follow.flush_secs.max(1)));
+    let mut flush = tokio::time::interval_at(tokio::time::Instant::now() + flush_period, flush_period);
+    let mut interrupted = std::pin::pin!(tokio::signal::ctrl_c());
 
-            for i in 0..res.len() {
-                let com_res = &res[i];
-                if com_res.pool_eq && com_res.road_addr_eq {
-                    calc_compare_res(&mut diff_amount_pers[i], com_res.diff_amount_per);
-                    calc_compare_res(&mut diff_fee_pers[i], com_res.diff_fee_per);
-                    calc_compare_res(&mut diff_impact_pers[i], com_res.diff_impact_per);
-                    calc_compare_res(&mut diff_inusd_pers[i], com_res.diff_inusd_per);
-                    calc_compare_res(&mut diff_ount_pers[i], com_res.diff_outusd_per);
-                    count_paths[i][1] += 1.0;
-                } else {
-                    count_paths[i][0] += 1.0;
+    if resumed.is_none() {
+        let _ = compare_detail_file.write_all("-------------------------Detail-----------------------\n".as_bytes());
+    }
+    if let Some(checkpointer) = checkpointer.as_mut() {
+        // Replaces any checkpoint left by an earlier run right away.
+        checkpointer.save()?;
+    }
+    loop {
+        let item = tokio::select! {
+            item = responses.next() => match item {
+                Some(item) => item,
+                None => break,
+            },
+            _ = flush.tick(), if following => {
+                flush_outputs(&mut json_detail, &mut csv)?;
+                write_reports(&config, started_at, &stats, &results, &mut compare_file)?;
+                // Latency and diff samples would otherwise grow for as long as the log is followed.
+                started_at = chrono::Utc::now();
+                stats = RunStats::new(&config);
+                results.clear();
+                continue;
+            }
+            _ = &mut interrupted, if following => {
+                info!("Interrupted, writing the final reports");
+                break;
+            }
+        };
+        // A followed log is compared until interrupted, `maxCount` only bounds finite runs.
+        if !following && index >= config.max_count {
+            break;
+        }
+        let (LogEntry { content: log_content, time: request_time, position }, res) = match item? {
+            Ok(response) => response,
+            Err(Undecodable { line, position, error }) => {
+                warn!("Fail to decode {}: {}", line, error);
+                stats.record_undecodable();
+                if let Some(checkpointer) = checkpointer.as_mut() {
+                    checkpointer.record(&JournalEntry::Undecodable { line }, position, index)?;
+                    if checkpointer.due() {
+                        flush_outputs(&mut json_detail, &mut csv)?;
+                        checkpointer.save()?;
+                    }
+                }
+                continue;
+            }
+        };
+        let request = serde_json::to_string(&log_content).unwrap();
+        match res {
+            Ok((old_res, new_res, timing)) => {
+                if let Some(checkpointer) = checkpointer.as_mut() {
+                    let entry = JournalEntry::Compared {
+                        index,
+                        request_time,
+                        request: log_content.clone(),
+                        timing: timing.clone(),
+                        old: old_res.clone(),
+                        new: new_res.clone(),
+                    };
+                    checkpointer.record(&entry, position, index + 1)?;
+                }
+                let compares = compare_results(
+                    index,
+                    request.clone(),
+                    &old_res,
+                    &new_res,
+                    &timing,
+                    config.max_compared_paths,
+                    &mut compare_detail_file,
+                );
+                let request_compare = RequestCompare {
+                    index,
+                    request_time,
+                    request: log_content,
+                    timing,
+                    compares,
+                };
+                stats.record_success(&request_compare, &old_res, &new_res);
+                if let Some(json_detail) = json_detail.as_mut() {
+                    json_detail.write(&request_compare, &old_res, &new_res)?;
+                }
+                if let Some(csv) = csv.as_mut() {
+                    csv.write(&request_compare, &old_res, &new_res)?;
+                }
+                if config.json_report_path.is_some() {
+                    results.push(request_compare);
+                }
+                index += 1;
+                // Stops before `buffered` pulls the next entry and calls the routers for it.
+                if !following && index >= config.max_count {
+                    break;
                 }
-                calc_compare_res(&mut sum_diff_amount_pers[i], com_res.diff_amount_per);
-                calc_compare_res(&mut sum_diff_fee_pers[i], com_res.diff_fee_per);
-                calc_compare_res(&mut sum_diff_impact_pers[i], com_res.diff_impact_per);
-                calc_compare_res(&mut sum_diff_inusd_pers[i], com_res.diff_inusd_per);
-                calc_compare_res(&mut sum_diff_ount_pers[i], com_res.diff_outusd_per);
             }
-            index += 1;
-        } else {
-            warn!("Fail to get response for {}", line_content);
+            Err(failure) => {
+                warn!("Fail to get response for {}: {}", request, failure);
+                stats.record_failure(&failure, &log_content);
+                if let Some(checkpointer) = checkpointer.as_mut() {
+                    let entry = JournalEntry::Failed {
+                        request: log_content,
+                        failure,
+                    };
+                    checkpointer.record(&entry, position, index)?;
+                }
+            }
+        }
+        if let Some(checkpointer) = checkpointer.as_mut().filter(|checkpointer| checkpointer.due()) {
+            flush_outputs(&mut json_detail, &mut csv)?;
+            checkpointer.save()?;
         }
     }
-    write_compare_result("Amount".to_owned(), &mut diff_amount_pers, &mut compare_file);
-    write_compare_result("Fee".to_owned(), &mut diff_fee_pers, &mut compare_file);
-    write_compare_result("impact".to_owned(), &mut diff_impact_pers, &mut compare_file);
-    write_compare_result("Inusd".to_owned(), &mut diff_inusd_pers, &mut compare_file);
-    write_compare_result("Outusd".to_owned(), &mut diff_ount_pers, &mut compare_file);
-    write_paths(&mut count_paths, &mut compare_file);
+    flush_outputs(&mut json_detail, &mut csv)?;
+    // Saved before the reports, resuming a finished run just writes them again.
+    if let Some(checkpointer) = checkpointer.as_mut() {
+        checkpointer.save()?;
+    }
+    write_reports(&config, started_at, &stats, &results, &mut compare_file)?;
+    Ok(stats)
+}
+
+/// Decodes lines as another thread reads them.
+fn decode_stream<'a>(
+    lines: impl Stream<Item = io::Result<(Option<LinePosition>, String)>> + 'a,
+    parser: &'a dyn LogParser,
+    time_range: TimeRange,
+    mut deduper: Deduper,
+) -> LocalBoxStream<'a, anyhow::Result<Result<LogEntry, Undecodable>>> {
+    lines
+        .filter_map(move |line| {
+            future::ready(match line {
+                Ok((position, line)) => decode_line(parser, &line, position, &time_range, &mut deduper).map(Ok),
+                Err(e) => Some(Err(e.into())),
+            })
+        })
+        .boxed_local()
+}
+
+/// A line matching the log format that fails to decode, counted and skipped instead of
+/// ending the run.
+struct Undecodable {
+    line: String,
+    position: Option<LinePosition>,
+    error: anyhow::Error,
+}
+
+/// Keeps the routing requests of a log line logged within `time_range`, decoded and
+/// deduplicated.
+fn decode_line(
+    parser: &dyn LogParser,
+    line_content: &str,
+    position: Option<LinePosition>,
+    time_range: &TimeRange,
+    deduper: &mut Deduper,
+) -> Option<Result<LogEntry, Undecodable>> {
+    if !parser.matches(line_content) {
+        return None;
+    }
+    let time = parser.time(line_content);
+    if !time_range.contains(time) {
+        return None;
+    }
+    let log_content = match parser.parse(line_content) {
+        Ok(log_content) => log_content,
+        Err(error) => return Some(Err(Undecodable { line: line_content.to_owned(), position, error })),
+    };
+    if !deduper.admit(&log_content) {
+        return None;
+    }
+    Some(Ok(LogEntry { content: log_content, time, position }))
+}
+
+fn flush_outputs(json_detail: &mut Option<JsonLinesWriter>, csv: &mut Option<CsvWriter>) -> anyhow::Result<()> {
+    if let Some(json_detail) = json_detail.as_mut() {
+        json_detail.flush()?;
+    }
+    if let Some(csv) = csv.as_mut() {
+        csv.flush()?;
+    }
+    Ok(())
+}
+
+/// Appends the text report and rewrites the other reports from `stats`.
+fn write_reports(
+    config: &Config,
+    started_at: chrono::DateTime<chrono::Utc>,
+    stats: &RunStats,
+    results: &[RequestCompare],
+    compare_file: &mut File,
+) -> anyhow::Result<()> {
+    let run = RunMetadata::new(started_at);
+    if config.follow.is_some() {
+        let _ = compare_file.write_all(
+            format!("-------------------Report {} ~ {}-----------------------------\n", run.started_at, run.finished_at).as_bytes()
+        );
+    }
+    write_compare_result("", &stats.same_path_diffs, compare_file);
+    write_distributions("", &stats.same_path_distributions, compare_file);
+    write_paths(&stats.paths, compare_file);
 
     let _ = compare_file.write_all("-------------------以下忽视路径是否相同，只是对比兑换出值-----------------------------\n".as_bytes());
-    write_compare_result("Sum Amount".to_owned(), &mut sum_diff_amount_pers, &mut compare_file);
-    write_compare_result("Sum Fee".to_owned(), &mut sum_diff_fee_pers, &mut compare_file);
-    write_compare_result("Sum impact".to_owned(), &mut sum_diff_impact_pers, &mut compare_file);
-    write_compare_result("Sum Inusd".to_owned(), &mut sum_diff_inusd_pers, &mut compare_file);
-    write_compare_result("Sum Outusd".to_owned(), &mut sum_diff_ount_pers, &mut compare_file);
+    write_compare_result("Sum ", &stats.all_path_diffs, compare_file);
+    write_distributions("Sum ", &stats.all_path_distributions, compare_file);
+    write_field_outcomes(&stats.field_outcomes, compare_file);
+    write_win_loss(&stats.win_loss, config.tie_tolerance, compare_file);
+    write_latency(&stats.latency, compare_file);
+    write_errors(&stats.errors, stats.undecodable, compare_file);
+    write_pairs("Token pairs", &stats.pairs_by_regression(), compare_file);
+    write_pairs("Token symbol pairs", &stats.symbol_pairs_by_regression(), compare_file);
+
+    if let Some(path) = config.json_report_path.as_ref() {
+        write_json_report(path, config, &run, stats, results)?;
+    }
+    if let Some(path) = config.html_report_path.as_ref() {
+        std::fs::write(path, render_html(config, &run, stats))?;
+    }
+    if let Some(path) = config.markdown_report_path.as_ref() {
+        std::fs::write(path, render_markdown(config, &run, stats))?;
+    }
+    if let Some(path) = config.regression_report_path.as_ref() {
+        std::fs::write(path, render_regressions(stats))?;
+    }
     Ok(())
 }
 
-fn write_compare_result(tag: String, pers: &mut Vec<Vec<f64>>, compare_res: &mut File) {
-    let _ = compare_res.write_all(format!("{}: diff\n", tag).as_bytes());
-    for i in 0..3 {
-        let res = compare_res_to_string(&mut pers[i]);
+fn write_win_loss(win_loss: &[WinLoss], tolerance: f64, compare_res: &mut File) {
+    let _ = compare_res.write_all(
+        format!("-------------------New vs old amount (tie within {}%)-----------------------------\n", tolerance * 100.0).as_bytes()
+    );
+    for (i, tally) in win_loss.iter().enumerate() {
+        let count = tally.count().max(1) as f64;
+        let _ = compare_res.write_all(
+            format!(
+                "path:{} sum:{}, new better:{}%, old better:{}%, tie:{}%, outUsd gained:{}, lost:{}, net:{}\n",
+                i,
+                tally.count(),
+                tally.new_better as f64 / count * 100.0,
+                tally.old_better as f64 / count * 100.0,
+                tally.tie as f64 / count * 100.0,
+                tally.usd_gained,
+                tally.usd_lost,
+                tally.usd_net(),
+            ).as_bytes()
+        );
+    }
+}
 
+fn write_pairs(title: &str, pairs: &[&PairStats], compare_res: &mut File) {
+    let _ = compare_res.write_all(
+        format!("-------------------{} (worst mean amount regression first)-----------------------------\n", title).as_bytes()
+    );
+    for pair in pairs {
         let _ = compare_res.write_all(
             format!(
-                "path:{}: {}\n",
-                i, res
+                "{}->{} ({}->{}) compared:{}, failed:{}, same path:{}%, mean amount regression:{}%\n",
+                pair.from_token,
+                pair.to_token,
+                pair.from_token_addr,
+                pair.to_token_addr,
+                pair.compared,
+                pair.failed,
+                pair.same_path_rate() * 100.0,
+                pair.mean_amount_regression * 100.0
             ).as_bytes()
         );
+        if let Some(per_path) = pair.all_path_diffs.by_metric.get(&Metric::Amount) {
+            for (i, histogram) in per_path.iter().enumerate() {
+                let _ = compare_res.write_all(format!("  Amount path:{}: {}\n", i, histogram).as_bytes());
+            }
+        }
     }
 }
 
-fn write_paths(path_diff: &mut Vec<Vec<f64>>, compare_res: &mut File) {
-    let _ = compare_res.write_all(format!("Pool and paths: diff\n").as_bytes());
-    for i in 0..3 {
-        let path = &mut path_diff[i];
-        let count: f64 = path.iter().sum();
-        for i in 0..path.len() {
-            path[i] /= count;
+fn write_errors(error_stats: &ErrorStats, undecodable: u64, compare_res: &mut File) {
+    let _ = compare_res.write_all("-------------------Router errors-----------------------------\n".as_bytes());
+    let _ = compare_res.write_all(format!("Undecodable log lines: {}\n", undecodable).as_bytes());
+    for (tag, classes) in [("Old", &error_stats.old), ("New", &error_stats.new)] {
+        let counts: Vec<String> = RouterError::KINDS
+            .iter()
+            .map(|kind| format!("{}:{}", kind, classes.get(kind).map_or(0, |class| class.count)))
+            .collect();
+        let _ = compare_res.write_all(format!("{}: {}\n", tag, counts.join(", ")).as_bytes());
+        for (kind, class) in classes.iter() {
+            for sample in class.samples.iter() {
+                let _ = compare_res.write_all(
+                    format!("{} {} sample: {} error: {}\n", tag, kind, sample.request, sample.error).as_bytes()
+                );
+            }
         }
+    }
+}
+
+fn write_field_outcomes(counts: &BTreeMap<&'static str, BTreeMap<FieldOutcome, u64>>, compare_res: &mut File) {
+    let _ = compare_res.write_all("-------------------Field outcomes-----------------------------\n".as_bytes());
+    for (field, outcomes) in counts.iter() {
+        let res: Vec<String> = FieldOutcome::ALL
+            .iter()
+            .map(|outcome| format!("{}:{}", outcome, outcomes.get(outcome).unwrap_or(&0)))
+            .collect();
+        let _ = compare_res.write_all(format!("{}: {}\n", field, res.join(", ")).as_bytes());
+    }
+}
+
+fn write_latency(latency_stats: &LatencyStats, compare_res: &mut File) {
+    let _ = compare_res.write_all("-------------------Latency(ms)-----------------------------\n".as_bytes());
+    for (tag, endpoint) in [("Old", &latency_stats.old), ("New", &latency_stats.new)] {
+        let summary = Summary::from_samples(&endpoint.costs).unwrap_or_default();
         let _ = compare_res.write_all(
             format!(
-                "path:{} sum:{}, diff:{}% same:{}%\n",
-                i,
-                count,
-                path[0] * 100.0,
-                path[1] * 100.0
+                "{}: {}, retries:{}, retried requests:{}\n",
+                tag, summary, endpoint.retries, endpoint.retried_requests
             ).as_bytes()
         );
+        for (path_count, costs) in endpoint.costs_by_paths.iter() {
+            let summary = Summary::from_samples(costs).unwrap_or_default();
+            let _ = compare_res.write_all(format!("{} paths:{}: {}\n", tag, path_count, summary).as_bytes());
+        }
     }
 }
 
-fn init_diff_pers() -> Vec<Vec<f64>> {
-    let mut diff_pers: Vec<Vec<f64>> = vec![];
-    diff_pers.push(vec![0.0; 8]);
-    diff_pers.push(vec![0.0; 8]);
-    diff_pers.push(vec![0.0; 8]);
-    diff_pers
+fn write_compare_result(tag_prefix: &str, histograms: &DiffHistograms, compare_res: &mut File) {
+    for (metric, per_path) in histograms.by_metric.iter() {
+        let _ = compare_res.write_all(format!("{}{}: diff\n", tag_prefix, metric.name()).as_bytes());
+        for (i, histogram) in per_path.iter().enumerate() {
+            let _ = compare_res.write_all(format!("path:{}: {}\n", i, histogram).as_bytes());
+        }
+    }
 }
 
-
-fn calc_compare_res(diff_pers: &mut Vec<f64>, diff_per: f64) {
-    let index;
-    if diff_per < 0.0001 {
-        index = 0;
-    } else if 0.0001 <= diff_per && diff_per < 0.001 {
-        index = 1;
-    } else if 0.001 <= diff_per && diff_per < 0.01 {
-        index = 2;
-    } else if 0.01 <= diff_per && diff_per < 0.02 {
-        index = 3;
-    } else if 0.02 <= diff_per && diff_per < 0.05 {
-        index = 4;
-    } else if 0.05 <= diff_per && diff_per < 0.1 {
-        index = 5;
-    } else {
-        index = 6
+fn write_distributions(tag_prefix: &str, distributions: &DiffDistributions, compare_res: &mut File) {
+    for (metric, per_path) in distributions.summaries() {
+        let _ = compare_res.write_all(format!("{}{}: distribution\n", tag_prefix, metric.name()).as_bytes());
+        for (i, summary) in per_path.iter().enumerate() {
+            let summary = summary.clone().unwrap_or_default();
+            let _ = compare_res.write_all(
+                format!(
+                    "path:{}: count:{}, mean:{}%, std:{}%, p50:{}%, p90:{}%, p99:{}%, p99.9:{}%, max:{}%\n",
+                    i,
+                    summary.count,
+                    summary.mean * 100.0,
+                    summary.std_dev * 100.0,
+                    summary.p50 * 100.0,
+                    summary.p90 * 100.0,
+                    summary.p99 * 100.0,
+                    summary.p999 * 100.0,
+                    summary.max * 100.0
+                ).as_bytes()
+            );
+        }
     }
-    diff_pers[index] += 1.0;
 }
 
-fn compare_res_to_string(diff_pers: &mut Vec<f64>) -> String {
-    let count: f64 = diff_pers.iter().sum();
-    for i in 0..diff_pers.len() {
-        diff_pers[i] /= count;
-    }
-    format!(
-        "count:{}, diff <0.01%: {}%,  0.01%~0.1%:{}%, 0.1%~1%:{}%, 1%~2%:{}%, 2%~5%:{}%, 5%~10%:{}%, >10%:{}%",
-        count,
-        diff_pers[0] * 100.0,
-        diff_pers[2] * 100.0,
-        diff_pers[3] * 100.0,
-        diff_pers[4] * 100.0,
-        diff_pers[5] * 100.0,
-        diff_pers[6] * 100.0,
-        diff_pers[7] * 100.0,
-    )
+fn write_paths(path_stats: &PathStats, compare_res: &mut File) {
+    let _ = compare_res.write_all("Pool and paths: diff\n".as_bytes());
+    for (i, equality) in path_stats.equality.iter().enumerate() {
+        let count = equality.count().max(1) as f64;
+        let _ = compare_res.write_all(
+            format!(
+                "path:{} sum:{}, diff:{}% same:{}%\n",
+                i,
+                equality.count(),
+                equality.differ as f64 / count * 100.0,
+                equality.same as f64 / count * 100.0
+            ).as_bytes()
+        );
+    }
+    let (requests, same, old_more, new_more) = path_stats.count_mismatch();
+    let requests_f = requests.max(1) as f64;
+    let _ = compare_res.write_all(
+        format!(
+            "Path count: sum:{}, same:{}%, old more:{}%, new more:{}%\n",
+            requests,
+            same as f64 / requests_f * 100.0,
+            old_more as f64 / requests_f * 100.0,
+            new_more as f64 / requests_f * 100.0
+        ).as_bytes()
+    );
+    for ((old, new), count) in path_stats.path_counts.iter() {
+        let _ = compare_res.write_all(format!("old paths:{} new paths:{} count:{}\n", old, new, count).as_bytes());
+    }
 }
 
 // fn update_clc_paths(path_size_pass: &mut Vec<i64>, path_size_count: &mut Vec<i64>, cast: i64, size: u32) {
@@ -230,37 +663,56 @@ fn compare_res_to_string(diff_pers: &mut Vec<f64>) -> String {
 
 
 async fn call_router_servers(
-    client: &mut RouterApiClient,
+    client: &RouterApiClient,
     log_content: &LogContent,
-) -> anyhow::Result<(RouterResult, RouterResult, i64)> {
-    let old_res = client.call_old_router(log_content).await?;
-    let t0 = chrono::Utc::now().timestamp_millis();
-    let new_res = client.call_new_router(log_content).await?;
-    let t1 = chrono::Utc::now().timestamp_millis();
-    Ok((old_res, new_res, t1 - t0))
+    simultaneous: bool,
+) -> Result<(RouterResult, RouterResult, RouterTiming), RouterFailure> {
+    call_both(client.call_old_router(log_content), client.call_new_router(log_content), simultaneous).await
 }
 
-fn decode_to_log_content(line: &str) -> anyhow::Result<LogContent> {
-    info!("Decode log {}", line);
-    let res: Vec<_> = line.split(GRAFANA_INFO_FLAG).collect();
-    if res.len() < 2 {
-        return Err(anyhow::Error::msg("Not contain --GRAFANA_INFO-- "));
-    }
-    let a: Value = serde_json::from_str(res[1])?;
-    if let Value::Object(map) = a {
-        let log_content_str = map.get(LOG_CONTENT_FLAG)
-            .ok_or(format_err!("fail to get logContent"))?
-            .as_str().ok_or(format_err!("fail to parse logContent to str "))?;
-        let log_content: LogContent = serde_json::from_str(log_content_str).unwrap();
-        info!("Decode result: log_content:{:?}", log_content);
-        return Ok(log_content);
-    }
-    return Err(format_err!("Fail to parse into json"));
+/// Runs the old and new router calls, at the same time or the new one after the old one, and
+/// times both whether they fail or not.
+async fn call_both<O, N>(old: O, new: N, simultaneous: bool) -> Result<(RouterResult, RouterResult, RouterTiming), RouterFailure>
+    where
+        O: Future<Output=(Result<RouterResult, RouterError>, u32)>,
+        N: Future<Output=(Result<RouterResult, RouterError>, u32)>, {
+    let (old, new) = if simultaneous {
+        futures::join!(timed(old), timed(new))
+    } else {
+        let old = timed(old).await;
+        let new = timed(new).await;
+        (old, new)
+    };
+    let ((old_res, old_retries), old_start, old_cost) = old;
+    let ((new_res, new_retries), new_start, new_cost) = new;
+    let timing = RouterTiming {
+        old_start,
+        old_cost,
+        new_start,
+        new_cost,
+        old_retries,
+        new_retries,
+    };
+    match (old_res, new_res) {
+        (Ok(old), Ok(new)) => Ok((old, new, timing)),
+        (old, new) => Err(RouterFailure {
+            old: old.err(),
+            new: new.err(),
+            timing,
+        }),
+    }
+}
+
+async fn timed<T, F: Future<Output=T>>(fut: F) -> (T, i64, i64) {
+    let t0 = chrono::Utc::now().timestamp_millis();
+    let res = fut.await;
+    let t1 = chrono::Utc::now().timestamp_millis();
+    (res, t0, t1 - t0)
 }
 
 fn get_output_files(config: &Config) -> (File, File) {
-    let compare_detail = OpenOptions::new().create(true).write(true).append(true).open(config.compare_res_detail_path.as_str()).unwrap();
-    let compare = OpenOptions::new().create(true).write(true).append(true).open(config.compare_res_path.as_str()).unwrap();
+    let compare_detail = OpenOptions::new().create(true).append(true).open(config.compare_res_detail_path.as_str()).unwrap();
+    let compare = OpenOptions::new().create(true).append(true).open(config.compare_res_path.as_str()).unwrap();
     (compare_detail, compare)
 }
 
@@ -296,31 +748,324 @@ fn compare_results(
     log_origin: String,
     old: &RouterResult,
     new: &RouterResult,
+    timing: &RouterTiming,
+    max_compared_paths: Option<usize>,
     compare_res: &mut File) -> Vec<CompareResult> {
-    let old_paths = old.data.clone().unwrap();
-    let new_paths = new.data.clone().unwrap();
-    let size = old_paths.len().min(new_paths.len());
-    let mut res: Vec<CompareResult> = vec![];
-    for i in 0..size {
-        let old_path = old_paths.get(i).unwrap();
-        let new_path = new_paths.get(i).unwrap();
-        let compare_op = CompareResult::gen_from_paths(old_path, new_path);
-        if compare_op.is_none() {
-            break;
-        }
-        let compare = compare_op.unwrap();
-        if compare.diff_amount_per > 0.01 && compare.pool_eq && compare.road_addr_eq {
-            let _ = compare_res.write_all(format!("origin log: {}, differ:{} \n", log_origin, compare.diff_amount_per).as_bytes());
+    let old_paths = old.data.as_deref().unwrap_or_default();
+    let new_paths = new.data.as_deref().unwrap_or_default();
+    let res = CompareResult::gen_from_results(old, new, max_compared_paths);
+    for (i, compare) in res.iter().enumerate() {
+        let old_path = &old_paths[i];
+        let new_path = &new_paths[i];
+        if compare.diff_amount_per.is_some_and(|per| per > 0.01) && compare.pool_eq && compare.road_addr_eq {
+            let _ = compare_res.write_all(format!(
+                "origin log: {}, differ:{} abs:{} \n",
+                log_origin,
+                compare.diff_amount_per.unwrap_or_default(),
+                compare.diff_amount.as_ref().map(|diff| diff.abs.clone()).unwrap_or_default()
+            ).as_bytes());
             let _ = compare_res.write_all(format!(
-                "index:{} path_index:{}\nold:{}\nnew:{}\n",
+                "index:{} path_index:{} old_cost:{}ms new_cost:{}ms start_skew:{}ms\nold:{}\nnew:{}\n",
                 index,
                 i,
+                timing.old_cost,
+                timing.new_cost,
+                timing.start_skew(),
                 serde_json::to_string(old_path).unwrap(),
                 serde_json::to_string(new_path).unwrap()
             ).as_bytes());
         }
-        res.push(compare);
     }
     res
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use serde_json::json;
+
+    /// Empty directory for the log and outputs of one test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("parse_logs_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Config reading `tron.log` of `dir` and writing every output there, `overrides` replacing
+    /// its fields.
+    fn test_config(dir: &Path, overrides: serde_json::Value) -> Config {
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let mut config = json!({
+            "oldUrl": "http://127.0.0.1:1/old/routingInV2",
+            "newUrl": "http://127.0.0.1:1/new/routingInV2",
+            "logFilePath": path("tron.log"),
+            "compareResPath": path("compare"),
+            "compareResDetailPath": path("compare_d"),
+            "jsonDetailPath": path("detail.jsonl"),
+            "jsonReportPath": path("report.json"),
+            "maxCount": 100,
+            "dedup": {"mode": "none"},
+        });
+        for (key, value) in overrides.as_object().unwrap() {
+            config[key] = value.clone();
+        }
+        test_support::config(config)
+    }
+
+    /// GRAFANA_INFO line requesting `amount` whole TRX.
+    fn log_line(second: u64, amount: u64) -> String {
+        let request = test_support::request("TRX", "USDT", &(amount * 1_000_000).to_string());
+        format!(
+            "2026-10-01 12:00:{:02}.000 INFO [http-nio] request-swap-routingInV2 --GRAFANA_INFO--{}\n",
+            second,
+            json!({ "logContent": serde_json::to_string(&request).unwrap() })
+        )
+    }
+
+    fn router_result(amount: f64) -> RouterResult {
+        serde_json::from_value(json!({
+            "code": 0,
+            "message": "ok",
+            "data": [{
+                "amount": amount.to_string(), "fee": "0.3", "impact": "0.1", "inUsd": "1", "outUsd": "1",
+                "pool": ["v2"], "roadForAddr": ["TTRX", "TUSDT"], "roadForName": ["TRX", "USDT"],
+            }],
+        }))
+        .unwrap()
+    }
+
+    /// Quotes 1% less on the new router for odd amounts; larger amounts answer sooner, so
+    /// concurrent calls complete out of log order.
+    async fn stub_call(log_content: LogContent) -> Result<(RouterResult, RouterResult, RouterTiming), RouterFailure> {
+        let amount = log_content.amount().unwrap();
+        tokio::time::sleep(Duration::from_millis(60u64.saturating_sub(amount as u64 * 5))).await;
+        let new_amount = if amount as u64 % 2 == 1 { amount * 0.99 } else { amount };
+        let timing = RouterTiming { old_cost: amount as i64, new_cost: amount as i64 * 2, ..Default::default() };
+        Ok((router_result(amount), router_result(new_amount), timing))
+    }
+
+    fn detail_indexes(dir: &Path) -> Vec<(u64, String)> {
+        std::fs::read_to_string(dir.join("detail.jsonl"))
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let detail: serde_json::Value = serde_json::from_str(line).unwrap();
+                (detail["index"].as_u64().unwrap(), detail["request"]["inAmount"].as_str().unwrap().to_owned())
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn concurrent_calls_keep_log_order_and_aggregates() {
+        let mut stats = vec![];
+        for concurrency in [1, 8] {
+            let dir = test_dir(&format!("order_{}", concurrency));
+            let log: String = (1..=10).map(|i| log_line(i, i)).collect();
+            std::fs::write(dir.join("tron.log"), log).unwrap();
+            let run = compare_logs(test_config(&dir, json!({ "maxConcurrency": concurrency })), stub_call).await.unwrap();
+
+            let expected: Vec<(u64, String)> = (1..=10).map(|i| (i - 1, (i * 1_000_000).to_string())).collect();
+            assert_eq!(detail_indexes(&dir), expected);
+            stats.push(serde_json::to_value(&run).unwrap());
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        assert_eq!(stats[0]["compared"], 10);
+        assert_eq!(stats[0], stats[1]);
+    }
+
+    #[tokio::test]
+    async fn max_count_stops_calling_the_routers() {
+        let dir = test_dir("max_count");
+        std::fs::write(dir.join("tron.log"), (1..=10).map(|i| log_line(i, i)).collect::<String>()).unwrap();
+        let calls = std::cell::Cell::new(0);
+        let run = compare_logs(test_config(&dir, json!({ "maxCount": 3 })), |log_content| {
+            calls.set(calls.get() + 1);
+            stub_call(log_content)
+        })
+        .await
+        .unwrap();
+
+        assert_eq!((run.compared, calls.get()), (3, 3));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_calls_count_in_latency() {
+        let dir = test_dir("failed_latency");
+        std::fs::write(dir.join("tron.log"), (1..=4).map(|i| log_line(i, i)).collect::<String>()).unwrap();
+        let run = compare_logs(test_config(&dir, json!({})), |log_content| async move {
+            if log_content.amount() != Some(3.0) {
+                return stub_call(log_content).await;
+            }
+            let timing = RouterTiming { old_cost: 3, new_cost: 15000, new_retries: 4, ..Default::default() };
+            Err(RouterFailure { old: None, new: Some(RouterError::Timeout), timing })
+        })
+        .await
+        .unwrap();
+
+        assert_eq!((run.compared, run.failed), (3, 1));
+        let new = &run.latency.new;
+        assert_eq!(new.costs.len(), 4);
+        assert_eq!(Summary::from_samples(&new.costs).unwrap().max, 15000.0);
+        assert_eq!((new.retries, new.retried_requests), (4, 1));
+        assert_eq!(new.costs_by_paths.values().map(Vec::len).sum::<usize>(), 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn undecodable_lines_are_counted_and_skipped() {
+        let dir = test_dir("undecodable");
+        let broken = log_line(3, 3);
+        let broken = &broken[..broken.find("inAmount").unwrap()];
+        let log: String = (1..=5).map(|i| if i == 3 { format!("{}\n", broken) } else { log_line(i, i) }).collect();
+        std::fs::write(dir.join("tron.log"), log).unwrap();
+
+        let checkpointed = json!({ "checkpointPath": dir.join("checkpoint.json"), "checkpointEvery": 1 });
+        let mut resumed = checkpointed.clone();
+        resumed["resume"] = json!(true);
+        let sampled = json!({ "sampling": {"mode": "reservoir", "seed": 1} });
+        for overrides in [checkpointed, resumed, sampled] {
+            let run = compare_logs(test_config(&dir, overrides.clone()), stub_call).await.unwrap();
+            assert_eq!((run.compared, run.undecodable), (4, 1), "{}", overrides);
+        }
+        let report = std::fs::read_to_string(dir.join("compare")).unwrap();
+        assert!(report.contains("Undecodable log lines: 1\n"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn truncated_query_string_does_not_end_the_run() {
+        let dir = test_dir("truncated_query");
+        let line = |second: u64, query: &str| {
+            format!("2026-10-01 12:00:{:02}.000 INFO gateway GET https://router/swap/routingInV2?{} 200 12ms\n", second, query)
+        };
+        let request = |amount: u64| {
+            format!(
+                "fromToken=TRX&toToken=USDT&fromTokenAddr=TTRX&toTokenAddr=TUSDT&inAmount={}&fromDecimal=6&toDecimal=6",
+                amount * 1_000_000
+            )
+        };
+        let log = [line(1, &request(1)), line(2, "fromToken=USDT&fro"), line(3, &request(2)), line(4, &request(4))].concat();
+        std::fs::write(dir.join("tron.log"), log).unwrap();
+
+        let config = test_config(&dir, json!({ "logFormat": {"format": "queryString"} }));
+        let run = compare_logs(config, stub_call).await.unwrap();
+        assert_eq!((run.compared, run.failed, run.undecodable), (3, 0, 1));
+        let amounts: Vec<String> = detail_indexes(&dir).into_iter().map(|(_, amount)| amount).collect();
+        assert_eq!(amounts, ["1000000", "2000000", "4000000"]);
+        assert!(std::fs::read_to_string(dir.join("compare")).unwrap().contains("Undecodable log lines: 1\n"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn resume_refuses_other_routers_and_changed_inputs() {
+        let dir = test_dir("resume_refused");
+        let log_path = dir.join("tron.log");
+        let log: String = (1..=4).map(|i| log_line(i, i)).collect();
+        std::fs::write(&log_path, &log).unwrap();
+        let checkpointed = json!({ "checkpointPath": dir.join("checkpoint.json") });
+        let mut resume = checkpointed.clone();
+        resume["resume"] = json!(true);
+        let resume_error = |overrides: serde_json::Value| async {
+            compare_logs(test_config(&dir, overrides), stub_call).await.unwrap_err().to_string()
+        };
+
+        compare_logs(test_config(&dir, checkpointed.clone()), stub_call).await.unwrap();
+        let mut other_router = resume.clone();
+        other_router["newUrl"] = json!("http://127.0.0.1:2/new/routingInV2");
+        other_router["simultaneousCalls"] = json!(true);
+        assert!(resume_error(other_router).await.starts_with("newUrl, simultaneousCalls differ"));
+
+        // A streamed run continues in a log that grew, not in a truncated or replaced one.
+        std::fs::write(&log_path, [log.as_str(), &log_line(5, 5)].concat()).unwrap();
+        assert_eq!(compare_logs(test_config(&dir, resume.clone()), stub_call).await.unwrap().compared, 5);
+        std::fs::write(&log_path, &log).unwrap();
+        assert!(resume_error(resume.clone()).await.contains("is shorter than at the checkpoint"));
+        std::fs::write(dir.join("tron.log.new"), [log.as_str(), &log_line(5, 5)].concat()).unwrap();
+        std::fs::rename(dir.join("tron.log.new"), &log_path).unwrap();
+        assert!(resume_error(resume.clone()).await.contains("rotated or replaced"));
+
+        // A sampled run would draw another sample from a log that grew.
+        let sampling = json!({"mode": "reservoir", "seed": 1});
+        let mut sampled = checkpointed;
+        sampled["sampling"] = sampling.clone();
+        compare_logs(test_config(&dir, sampled), stub_call).await.unwrap();
+        std::fs::write(&log_path, [log.as_str(), &log_line(5, 5), &log_line(6, 6)].concat()).unwrap();
+        resume["sampling"] = sampling;
+        assert!(resume_error(resume).await.contains("grew since the sampled run started"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn resumed_run_matches_an_uninterrupted_one() {
+        let log: String = (1..=12).map(|i| log_line(i, i)).collect();
+        let full_dir = test_dir("resume_full");
+        std::fs::write(full_dir.join("tron.log"), &log).unwrap();
+        let full = compare_logs(test_config(&full_dir, json!({})), stub_call).await.unwrap();
+
+        // Hangs on the 8th call after checkpoints at 3 and 6 entries, the 7th only reaching the
+        // journal and outputs.
+        let dir = test_dir("resume");
+        std::fs::write(dir.join("tron.log"), &log).unwrap();
+        let overrides = json!({ "checkpointPath": dir.join("checkpoint.json"), "checkpointEvery": 3 });
+        let calls = std::cell::Cell::new(0);
+        let interrupted = compare_logs(test_config(&dir, overrides.clone()), |log_content| {
+            calls.set(calls.get() + 1);
+            let hang = calls.get() > 7;
+            async move {
+                if hang {
+                    future::pending::<()>().await;
+                }
+                stub_call(log_content).await
+            }
+        });
+        assert!(tokio::time::timeout(Duration::from_secs(2), interrupted).await.is_err());
+
+        let mut resume = overrides;
+        resume["resume"] = json!(true);
+        let mut changed = resume.clone();
+        changed["dedup"] = json!({"mode": "pair"});
+        let error = compare_logs(test_config(&dir, changed), stub_call).await.unwrap_err();
+        assert!(error.to_string().starts_with("dedup differ"), "{}", error);
+
+        let resumed = compare_logs(test_config(&dir, resume), stub_call).await.unwrap();
+        assert_eq!(serde_json::to_value(&resumed).unwrap(), serde_json::to_value(&full).unwrap());
+        assert_eq!(detail_indexes(&dir), detail_indexes(&full_dir));
+        std::fs::remove_dir_all(&full_dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn simultaneous_calls_start_together_and_failures_keep_timing() {
+        async fn call(millis: u64, result: Result<RouterResult, RouterError>) -> (Result<RouterResult, RouterError>, u32) {
+            tokio::time::sleep(Duration::from_millis(millis)).await;
+            (result, 0)
+        }
+
+        let (_, _, timing) = call_both(call(50, Ok(router_result(1.0))), call(50, Ok(router_result(1.0))), true).await.unwrap();
+        assert!(timing.start_skew() <= 5, "{:?}", timing);
+        assert!(timing.old_cost >= 50 && timing.new_cost >= 50, "{:?}", timing);
+
+        let (_, _, timing) = call_both(call(50, Ok(router_result(1.0))), call(50, Ok(router_result(1.0))), false).await.unwrap();
+        assert!(timing.new_start >= timing.old_start + timing.old_cost, "{:?}", timing);
+
+        for simultaneous in [true, false] {
+            let failure = call_both(call(50, Err(RouterError::Timeout)), call(30, Ok(router_result(1.0))), simultaneous)
+                .await
+                .unwrap_err();
+            assert_eq!((failure.old, failure.new), (Some(RouterError::Timeout), None));
+            assert!(failure.timing.old_cost >= 50 && failure.timing.new_cost >= 30, "{:?}", failure.timing);
+        }
+    }
+
+    #[tokio::test]
+    async fn stdin_runs_cannot_be_checkpointed() {
+        let dir = test_dir("stdin_checkpoint");
+        let config = test_config(&dir, json!({ "logFilePath": "-", "checkpointPath": dir.join("checkpoint.json") }));
+        let err = compare_logs(config, stub_call).await.unwrap_err();
+        assert!(err.to_string().contains("checkpoints need log files"), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -9,28 +9,48 @@ use parse_logs::RouterApiClient;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("only need one arg used as config file");
+    let mut args: Vec<String> = env::args().collect();
+    let resume = args.iter().any(|arg| arg == "--resume");
+    args.retain(|arg| arg != "--resume");
+    let since = take_flag(&mut args, "--since");
+    let until = take_flag(&mut args, "--until");
+    if args.len() != 2 && args.len() != 3 {
+        println!("usage: parse_logs <config file> [log file, - for stdin] [--resume] [--since <time>] [--until <time>]");
+        println!("  times are RFC 3339, '2026-10-01 12:00:00' or '2026-10-01' in local time, or a duration ago such as 30m, 2h or 1d");
         return Ok(());
     }
-    let config_file = (&args[1]).clone();
-    let config = Config::from_file(config_file.as_str());
+    let config_file = args[1].clone();
+    let mut config = Config::from_file(config_file.as_str());
+    // A log given on the command line replaces the configured ones.
+    if let Some(log_file_path) = args.get(2) {
+        config.log_file_path = log_file_path.clone();
+        config.log_file_paths.clear();
+    }
+    config.resume |= resume;
+    config.since = since.or(config.since);
+    config.until = until.or(config.until);
     init_log("info");
-    let mut client = RouterApiClient::new(
+    let client = RouterApiClient::new(
         Url::parse(config.old_url.as_str()).expect("decode old url fail"),
         Url::parse(config.new_url.as_str()).expect("decode new url fail"),
         config.use_base_tokens.clone(),
         Duration::from_secs(15),
     );
-    parse_logs_fn(&mut client, config).await?;
+    parse_logs_fn(&client, config).await?;
     Ok(())
 }
 
+/// Removes `flag` and the value following it from `args`.
+fn take_flag(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let at = args.iter().position(|arg| arg == flag)?;
+    args.remove(at);
+    (at < args.len()).then(|| args.remove(at))
+}
+
 #[tokio::test]
 async fn test_client() {
     use parse_logs::LogContent;
-    let mut client = RouterApiClient::new(
+    let client = RouterApiClient::new(
         Url::parse("http://127.0.0.1:8080/routingInV2").expect("decode old url fail"),
         Url::parse("http://127.0.0.1:8080/routingInV2").expect("decode new url fail"),
         "true".to_owned(),
@@ -44,7 +64,9 @@ async fn test_client() {
         in_amount: "20000000".to_string(),
         from_decimal: 6,
         to_decimal: 18,
+        use_base_tokens: None,
     };
-    let response = client.call_new_router(&log_client).await.expect("Fail to get response");
+    let (response, _) = client.call_new_router(&log_client).await;
+    let response = response.expect("Fail to get response");
     println!("{:?}", response);
 }
\ No newline at end of file
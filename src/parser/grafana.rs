@@ -0,0 +1,37 @@
+use anyhow::format_err;
+use log::info;
+use serde_json::Value;
+
+use crate::types::LogContent;
+use super::LogParser;
+
+/// Lines logged through the GRAFANA_INFO logger, the request JSON nested as a string in the
+/// JSON object following `separator`.
+pub struct GrafanaParser {
+    pub marker: String,
+    pub separator: String,
+    pub field: String,
+}
+
+impl LogParser for GrafanaParser {
+    fn matches(&self, line: &str) -> bool {
+        line.contains(&self.marker)
+    }
+
+    fn parse(&self, line: &str) -> anyhow::Result<LogContent> {
+        info!("Decode log {}", line);
+        let (_, payload) = line
+            .split_once(&self.separator)
+            .ok_or_else(|| format_err!("Not contain {} ", self.separator))?;
+        let a: Value = serde_json::from_str(payload)?;
+        if let Value::Object(map) = a {
+            let log_content_str = map.get(&self.field)
+                .ok_or(format_err!("fail to get {}", self.field))?
+                .as_str().ok_or(format_err!("fail to parse {} to str ", self.field))?;
+            let log_content: LogContent = serde_json::from_str(log_content_str)?;
+            info!("Decode result: log_content:{:?}", log_content);
+            return Ok(log_content);
+        }
+        Err(format_err!("Fail to parse into json"))
+    }
+}
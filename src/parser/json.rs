@@ -0,0 +1,38 @@
+use anyhow::format_err;
+use chrono::{DateTime, FixedOffset};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::types::LogContent;
+use super::LogParser;
+
+/// One JSON object per line, the request being the object itself or the value of `field`.
+pub struct JsonLinesParser {
+    pub marker: Option<String>,
+    pub field: Option<String>,
+    pub time_field: String,
+}
+
+impl LogParser for JsonLinesParser {
+    fn matches(&self, line: &str) -> bool {
+        line.trim_start().starts_with('{') && self.marker.as_ref().is_none_or(|marker| line.contains(marker.as_str()))
+    }
+
+    fn time(&self, line: &str) -> Option<DateTime<FixedOffset>> {
+        let object: Value = serde_json::from_str(line).ok()?;
+        DateTime::parse_from_rfc3339(object.get(&self.time_field)?.as_str()?).ok()
+    }
+
+    fn parse(&self, line: &str) -> anyhow::Result<LogContent> {
+        let object: Value = serde_json::from_str(line)?;
+        let request = match self.field.as_ref() {
+            Some(field) => object.get(field).ok_or_else(|| format_err!("fail to get {}", field))?,
+            None => &object,
+        };
+        // Loggers that cannot nest objects write the request as a JSON string instead.
+        Ok(match request {
+            Value::String(request) => serde_json::from_str(request)?,
+            request => LogContent::deserialize(request)?,
+        })
+    }
+}
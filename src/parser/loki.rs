@@ -0,0 +1,41 @@
+use chrono::{DateTime, FixedOffset};
+use serde::Deserialize;
+
+use crate::types::LogContent;
+use super::LogParser;
+
+/// One exported entry of `logcli query --output jsonl`.
+#[derive(Deserialize)]
+struct LokiEntry {
+    #[serde(rename = "line")]
+    line: String,
+    #[serde(rename = "timestamp", default)]
+    timestamp: Option<String>,
+}
+
+/// Loki exports wrapping lines of another format.
+pub struct LokiParser {
+    pub inner: Box<dyn LogParser>,
+}
+
+impl LogParser for LokiParser {
+    /// The marker is looked for in the escaped line, which only differs for markers with
+    /// quotes or backslashes.
+    fn matches(&self, line: &str) -> bool {
+        self.inner.matches(line)
+    }
+
+    /// The time Loki received the line, falling back to the timestamp of the line itself.
+    fn time(&self, line: &str) -> Option<DateTime<FixedOffset>> {
+        let entry: LokiEntry = serde_json::from_str(line).ok()?;
+        entry
+            .timestamp
+            .and_then(|timestamp| DateTime::parse_from_rfc3339(&timestamp).ok())
+            .or_else(|| self.inner.time(&entry.line))
+    }
+
+    fn parse(&self, line: &str) -> anyhow::Result<LogContent> {
+        let entry: LokiEntry = serde_json::from_str(line)?;
+        self.inner.parse(&entry.line)
+    }
+}
@@ -0,0 +1,99 @@
+use chrono::{DateTime, FixedOffset};
+
+use crate::timerange::line_time;
+use crate::types::{LogContent, LogFormat};
+
+pub mod grafana;
+pub mod json;
+pub mod loki;
+pub mod nginx;
+pub mod query;
+
+/// Finds and decodes the routing requests of one log format.
+pub trait LogParser {
+    /// Cheap check for a routing request, lines failing it are skipped without decoding.
+    fn matches(&self, line: &str) -> bool;
+
+    /// When `line` was logged, `None` when it has no timestamp.
+    fn time(&self, line: &str) -> Option<DateTime<FixedOffset>> {
+        line_time(line)
+    }
+
+    /// Decodes a line that `matches`.
+    fn parse(&self, line: &str) -> anyhow::Result<LogContent>;
+}
+
+pub fn log_parser(format: &LogFormat) -> Box<dyn LogParser> {
+    match format {
+        LogFormat::Grafana { marker, separator, field } => Box::new(grafana::GrafanaParser {
+            marker: marker.clone(),
+            separator: separator.clone(),
+            field: field.clone(),
+        }),
+        LogFormat::JsonLines { marker, field, time_field } => Box::new(json::JsonLinesParser {
+            marker: marker.clone(),
+            field: field.clone(),
+            time_field: time_field.clone(),
+        }),
+        LogFormat::Nginx { marker } => Box::new(nginx::NginxParser { marker: marker.clone() }),
+        LogFormat::QueryString { marker } => Box::new(query::QueryStringParser { marker: marker.clone() }),
+        LogFormat::Loki { inner } => Box::new(loki::LokiParser { inner: log_parser(inner) }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(format: &str, line: &str) -> (Option<DateTime<FixedOffset>>, LogContent) {
+        let parser = log_parser(&serde_json::from_str(format).unwrap());
+        assert!(parser.matches(line), "{} does not match {}", format, line);
+        (parser.time(line), parser.parse(line).unwrap())
+    }
+
+    #[test]
+    fn built_in_formats_decode_the_same_request() {
+        let request = r#"{"fromToken":"USDT","toToken":"TUSD","fromTokenAddr":"TUSDT","toTokenAddr":"TTUSD","inAmount":"1000000","fromDecimal":6,"toDecimal":18}"#;
+        let expected = serde_json::to_string(&serde_json::from_str::<LogContent>(request).unwrap()).unwrap();
+        let grafana = format!(
+            "2026-10-01T12:00:00+08:00 INFO request-swap-routingInV2 --GRAFANA_INFO--{}",
+            serde_json::json!({ "logContent": request })
+        );
+        let cases = [
+            (r#"{"format": "grafana"}"#.to_owned(), grafana.clone()),
+            (
+                r#"{"format": "jsonLines", "field": "request"}"#.to_owned(),
+                format!(r#"{{"time":"2026-10-01T12:00:00+08:00","request":{}}}"#, request),
+            ),
+            (
+                r#"{"format": "nginx"}"#.to_owned(),
+                "10.0.0.1 - - [01/Oct/2026:12:00:00 +0800] \"GET /swap/routingInV2?fromToken=USDT&fromTokenAddr=TUSDT&toToken=TUSD&toTokenAddr=TTUSD&inAmount=1000000&fromDecimal=6&toDecimal=18 HTTP/1.1\" 200 512 \"-\" \"curl/8.0\"".to_owned(),
+            ),
+            (
+                r#"{"format": "loki"}"#.to_owned(),
+                serde_json::json!({ "labels": {}, "line": grafana, "timestamp": "2026-10-01T04:00:00Z" }).to_string(),
+            ),
+        ];
+        for (format, line) in cases.iter() {
+            let (time, log_content) = decode(format, line);
+            assert_eq!(time.map(|time| time.timestamp()), Some(1790827200), "{}", format);
+            assert_eq!(serde_json::to_string(&log_content).unwrap(), expected, "{}", format);
+        }
+        assert!(!log_parser(&LogFormat::default()).matches("2026-10-01 12:00:00 INFO started"));
+    }
+
+    #[test]
+    fn query_strings_are_url_decoded() {
+        let line = "2026-10-01 12:00:00.123 INFO gateway GET https://router/swap/routingInV2?toToken=TUSD&fromToken=USD%E2%82%AE&fromTokenAddr=TUSDT&toTokenAddr=TTUSD&inAmount=1000000&fromDecimal=6&toDecimal=18&useBaseTokens=false 200 12ms";
+        let (time, log_content) = decode(r#"{"format": "queryString"}"#, line);
+        assert_eq!(time.unwrap().format("%Y-%m-%d %H:%M:%S%.3f").to_string(), "2026-10-01 12:00:00.123");
+        assert_eq!(log_content.from_token, "USD₮");
+        assert_eq!(log_content.to_decimal, 18);
+        assert_eq!(log_content.use_base_tokens.as_deref(), Some("false"));
+
+        let without_base_tokens = line.replace("&useBaseTokens=false", "");
+        assert_eq!(decode(r#"{"format": "queryString"}"#, &without_base_tokens).1.use_base_tokens, None);
+        let missing_amount = line.replace("&inAmount=1000000", "");
+        assert!(log_parser(&LogFormat::QueryString { marker: "routingInV2?".to_owned() }).parse(&missing_amount).is_err());
+    }
+}
@@ -0,0 +1,38 @@
+use anyhow::format_err;
+use chrono::{DateTime, FixedOffset};
+
+use crate::types::LogContent;
+use super::query::decode_query;
+use super::LogParser;
+
+/// `$time_local` of the combined log format.
+const TIME_LOCAL_FORMAT: &str = "%d/%b/%Y:%H:%M:%S %z";
+
+/// nginx access logs in the combined format,
+/// `$remote_addr - $remote_user [$time_local] "$request" $status ...`.
+pub struct NginxParser {
+    pub marker: String,
+}
+
+impl LogParser for NginxParser {
+    fn matches(&self, line: &str) -> bool {
+        line.contains(&self.marker)
+    }
+
+    fn time(&self, line: &str) -> Option<DateTime<FixedOffset>> {
+        let (_, rest) = line.split_once('[')?;
+        let (time_local, _) = rest.split_once(']')?;
+        DateTime::parse_from_str(time_local, TIME_LOCAL_FORMAT).ok()
+    }
+
+    fn parse(&self, line: &str) -> anyhow::Result<LogContent> {
+        // `"GET /swap/routingInV2?fromToken=... HTTP/1.1"`
+        let request = line.split('"').nth(1).ok_or_else(|| format_err!("no request line in {}", line))?;
+        let uri = request
+            .split_whitespace()
+            .nth(1)
+            .ok_or_else(|| format_err!("no uri in request line {}", request))?;
+        let (_, query) = uri.split_once('?').ok_or_else(|| format_err!("no query string in {}", uri))?;
+        decode_query(query)
+    }
+}
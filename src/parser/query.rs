@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use anyhow::format_err;
+use reqwest::Url;
+
+use crate::types::LogContent;
+use super::LogParser;
+
+/// Lines holding a router call URL anywhere, e.g. gateway access logs. The query string
+/// follows `marker` and ends at the first whitespace or quote.
+pub struct QueryStringParser {
+    pub marker: String,
+}
+
+impl LogParser for QueryStringParser {
+    fn matches(&self, line: &str) -> bool {
+        line.contains(&self.marker)
+    }
+
+    fn parse(&self, line: &str) -> anyhow::Result<LogContent> {
+        let (_, rest) = line
+            .split_once(&self.marker)
+            .ok_or_else(|| format_err!("Not contain {} ", self.marker))?;
+        let query = rest
+            .split(|c: char| c.is_whitespace() || c == '"' || c == '\'')
+            .next()
+            .unwrap_or_default();
+        decode_query(query)
+    }
+}
+
+/// Reverses `gen_url`: reads the request from a URL encoded query string, in any order and
+/// with or without `useBaseTokens`.
+pub fn decode_query(query: &str) -> anyhow::Result<LogContent> {
+    let url = Url::parse(&format!("http://localhost/?{}", query))?;
+    let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+    let param = |name: &str| params.get(name).cloned().ok_or_else(|| format_err!("no {} in {}", name, query));
+    let decimal = |name: &str| -> anyhow::Result<u16> {
+        let value = param(name)?;
+        value.parse().map_err(|e| format_err!("invalid {} {}: {}", name, value, e))
+    };
+    Ok(LogContent {
+        from_token: param("fromToken")?,
+        to_token: param("toToken")?,
+        from_token_addr: param("fromTokenAddr")?,
+        to_token_addr: param("toTokenAddr")?,
+        in_amount: param("inAmount")?,
+        from_decimal: decimal("fromDecimal")?,
+        to_decimal: decimal("toDecimal")?,
+        use_base_tokens: params.get("useBaseTokens").cloned(),
+    })
+}
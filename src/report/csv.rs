@@ -0,0 +1,156 @@
+use std::fs::{File, OpenOptions};
+use anyhow::format_err;
+
+use crate::types::{CompareResult, Metric, Path, RequestCompare, RouterResult};
+
+const FIXED_HEADERS: [&str; 14] = [
+    "index",
+    "requestTime",
+    "pathIndex",
+    "fromToken",
+    "toToken",
+    "fromTokenAddr",
+    "toTokenAddr",
+    "inAmount",
+    "fromDecimal",
+    "toDecimal",
+    "poolEq",
+    "roadForAddrEq",
+    "oldPathCount",
+    "newPathCount",
+];
+
+/// Writes one row per `(request index, path index)`; the column order is fixed so files from
+/// different runs can be concatenated.
+pub struct CsvWriter {
+    writer: csv::Writer<File>,
+}
+
+impl CsvWriter {
+    /// Opens `path` for appending, the header is only written to an empty file. A file with
+    /// other columns, e.g. written by an older version, is refused rather than misaligned.
+    pub fn open(path: &str) -> anyhow::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let is_empty = file.metadata()?.len() == 0;
+        if !is_empty {
+            let mut reader = csv::Reader::from_path(path)?;
+            if !reader.headers()?.iter().eq(headers().iter()) {
+                return Err(format_err!("{} has other columns than the ones written now, move it away or set another csvPath", path));
+            }
+        }
+        let mut writer = csv::WriterBuilder::new().quote_style(csv::QuoteStyle::Necessary).from_writer(file);
+        if is_empty {
+            writer.write_record(headers())?;
+        }
+        Ok(Self { writer })
+    }
+
+    pub fn write(&mut self, compare: &RequestCompare, old: &RouterResult, new: &RouterResult) -> anyhow::Result<()> {
+        let old_paths = old.data.as_deref().unwrap_or_default();
+        let new_paths = new.data.as_deref().unwrap_or_default();
+        for (i, path_compare) in compare.compares.iter().enumerate() {
+            self.writer.write_record(row(
+                compare,
+                i,
+                path_compare,
+                &old_paths[i],
+                &new_paths[i],
+                old_paths.len(),
+                new_paths.len(),
+            ))?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+fn headers() -> Vec<String> {
+    let mut headers: Vec<String> = FIXED_HEADERS.iter().map(|h| h.to_string()).collect();
+    for metric in Metric::ALL {
+        let name = metric.name();
+        headers.push(format!("old{}", name));
+        headers.push(format!("new{}", name));
+        headers.push(format!("delta{}", name));
+        headers.push(format!("diff{}Per", name));
+    }
+    headers
+}
+
+fn row(
+    compare: &RequestCompare,
+    path_index: usize,
+    path_compare: &CompareResult,
+    old: &Path,
+    new: &Path,
+    old_path_count: usize,
+    new_path_count: usize,
+) -> Vec<String> {
+    let request = &compare.request;
+    let mut row = vec![
+        compare.index.to_string(),
+        compare.request_time.map(|time| time.to_rfc3339()).unwrap_or_default(),
+        path_index.to_string(),
+        request.from_token.clone(),
+        request.to_token.clone(),
+        request.from_token_addr.clone(),
+        request.to_token_addr.clone(),
+        request.in_amount.clone(),
+        request.from_decimal.to_string(),
+        request.to_decimal.to_string(),
+        path_compare.pool_eq.to_string(),
+        path_compare.road_addr_eq.to_string(),
+        old_path_count.to_string(),
+        new_path_count.to_string(),
+    ];
+    for metric in Metric::ALL {
+        row.push(old.value(metric).cloned().unwrap_or_default());
+        row.push(new.value(metric).cloned().unwrap_or_default());
+        row.push(path_compare.diff(metric).map(|diff| diff.delta.to_string()).unwrap_or_default());
+        row.push(path_compare.diff_per(metric).map(|per| per.to_string()).unwrap_or_default());
+    }
+    row
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn rows_are_quoted_and_appended_under_the_same_header() {
+        let path = std::env::temp_dir().join(format!("parse_logs_csv_{}.csv", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+        let result: RouterResult =
+            serde_json::from_value(serde_json::json!({ "code": 0, "message": "ok", "data": [{ "amount": "100" }] })).unwrap();
+        let compare = RequestCompare {
+            index: 0,
+            request_time: None,
+            request: test_support::request("TRX", "US\"D,T", "1"),
+            timing: Default::default(),
+            compares: CompareResult::gen_from_results(&result, &result, None),
+        };
+        for _ in 0..2 {
+            let mut writer = CsvWriter::open(path).unwrap();
+            writer.write(&compare, &result, &result).unwrap();
+            writer.flush().unwrap();
+        }
+
+        let mut reader = csv::Reader::from_path(path).unwrap();
+        assert!(reader.headers().unwrap().iter().eq(headers().iter()));
+        let rows: Vec<csv::StringRecord> = reader.records().collect::<Result<_, _>>().unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(&rows[1][4], "US\"D,T");
+        assert_eq!(&rows[1][FIXED_HEADERS.len()], "100");
+
+        // Written before `requestTime` was added.
+        let old_header: Vec<String> = headers().into_iter().filter(|header| header != "requestTime").collect();
+        std::fs::write(path, old_header.join(",") + "\n").unwrap();
+        assert!(CsvWriter::open(path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
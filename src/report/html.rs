@@ -0,0 +1,310 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::client::error::RouterError;
+use crate::stats::{DiffDistributions, EndpointLatency, ErrorClass, Histogram, PairStats, RunStats, Summary};
+use crate::types::{Config, Metric};
+use super::RunMetadata;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin:1em 0}\
+th,td{border:1px solid #ccc;padding:4px 8px;text-align:right}\
+th:first-child,td:first-child{text-align:left}\
+h2{border-bottom:2px solid #4a7;padding-bottom:4px}\
+.charts{display:flex;flex-wrap:wrap;gap:1em}\
+.chart{border:1px solid #eee;padding:8px}\
+.chart h4{margin:0 0 4px 0}";
+
+const BAR_WIDTH: f64 = 240.0;
+const BAR_HEIGHT: usize = 18;
+const LABEL_WIDTH: usize = 110;
+
+/// Renders the run as a single HTML page; charts are inline SVG so the file has no
+/// external assets.
+pub fn render_html(config: &Config, run: &RunMetadata, stats: &RunStats) -> String {
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Router comparison</title><style>{}</style></head><body>",
+        STYLE
+    );
+    html.push_str("<h1>Router comparison</h1>");
+    let _ = write!(
+        html,
+        "<table><tr><th>Old router</th><td>{}</td></tr><tr><th>New router</th><td>{}</td></tr>\
+         <tr><th>Started</th><td>{}</td></tr><tr><th>Duration</th><td>{} ms</td></tr>\
+         <tr><th>Compared requests</th><td>{}</td></tr><tr><th>Failed requests</th><td>{}</td></tr>\
+         <tr><th>Undecodable lines</th><td>{}</td></tr></table>",
+        escape(&config.old_url),
+        escape(&config.new_url),
+        escape(&run.started_at),
+        run.duration_ms,
+        stats.compared,
+        stats.failed,
+        stats.undecodable
+    );
+
+    render_diffs(&mut html, "Diffs of paths with the same pool and road", &stats.same_path_diffs.by_metric);
+    render_diffs(&mut html, "Diffs of all paths", &stats.all_path_diffs.by_metric);
+    render_distributions(&mut html, "Diff percentiles of paths with the same pool and road", &stats.same_path_distributions);
+    render_distributions(&mut html, "Diff percentiles of all paths", &stats.all_path_distributions);
+    render_paths(&mut html, stats);
+    render_win_loss(&mut html, stats, config.tie_tolerance);
+    render_worst(&mut html, stats);
+    render_pairs(&mut html, "Token pairs", &stats.pairs_by_regression());
+    render_pairs(&mut html, "Token symbol pairs", &stats.symbol_pairs_by_regression());
+    render_latency(&mut html, stats);
+    render_errors(&mut html, stats);
+    html.push_str("</body></html>");
+    html
+}
+
+fn render_diffs(html: &mut String, title: &str, by_metric: &BTreeMap<Metric, Vec<Histogram>>) {
+    let _ = write!(html, "<h2>{}</h2>", escape(title));
+    for (metric, histograms) in by_metric {
+        let _ = write!(html, "<h3>{}</h3><div class=\"charts\">", metric.name());
+        for (i, histogram) in histograms.iter().enumerate() {
+            let _ = write!(
+                html,
+                "<div class=\"chart\"><h4>path {} (count {})</h4>{}</div>",
+                i,
+                histogram.count(),
+                bar_chart(&histogram.labels(), &histogram.percentages(), "%")
+            );
+        }
+        html.push_str("</div>");
+    }
+}
+
+fn render_distributions(html: &mut String, title: &str, distributions: &DiffDistributions) {
+    let _ = write!(
+        html,
+        "<h2>{}</h2><table><tr><th>metric</th><th>path</th><th>count</th><th>mean</th><th>std</th><th>p50</th>\
+         <th>p90</th><th>p99</th><th>p99.9</th><th>max</th></tr>",
+        escape(title)
+    );
+    for (metric, per_path) in distributions.summaries() {
+        for (i, summary) in per_path.into_iter().enumerate() {
+            let summary = summary.unwrap_or_default();
+            let _ = write!(html, "<tr><td>{}</td><td>{}</td><td>{}</td>", metric.name(), i, summary.count);
+            for value in [summary.mean, summary.std_dev, summary.p50, summary.p90, summary.p99, summary.p999, summary.max] {
+                let _ = write!(html, "<td>{:.4}%</td>", value * 100.0);
+            }
+            html.push_str("</tr>");
+        }
+    }
+    html.push_str("</table>");
+}
+
+fn render_paths(html: &mut String, stats: &RunStats) {
+    html.push_str("<h2>Pool and road equality</h2><table><tr><th>path</th><th>count</th><th>same</th><th>differ</th></tr>");
+    for (i, equality) in stats.paths.equality.iter().enumerate() {
+        let count = equality.count().max(1) as f64;
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{:.2}%</td><td>{:.2}%</td></tr>",
+            i,
+            equality.count(),
+            equality.same as f64 / count * 100.0,
+            equality.differ as f64 / count * 100.0
+        );
+    }
+    html.push_str("</table><table><tr><th>old paths</th><th>new paths</th><th>requests</th></tr>");
+    for ((old, new), count) in stats.paths.path_counts.iter() {
+        let _ = write!(html, "<tr><td>{}</td><td>{}</td><td>{}</td></tr>", old, new, count);
+    }
+    html.push_str("</table>");
+}
+
+fn render_win_loss(html: &mut String, stats: &RunStats, tolerance: f64) {
+    let _ = write!(
+        html,
+        "<h2>New vs old amount (tie within {}%)</h2><table><tr><th>path</th><th>count</th><th>new better</th>\
+         <th>old better</th><th>tie</th><th>outUsd gained</th><th>outUsd lost</th><th>net</th></tr>",
+        tolerance * 100.0
+    );
+    for (i, tally) in stats.win_loss.iter().enumerate() {
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            i,
+            tally.count(),
+            tally.new_better,
+            tally.old_better,
+            tally.tie,
+            tally.usd_gained,
+            tally.usd_lost,
+            tally.usd_net()
+        );
+    }
+    html.push_str("</table>");
+}
+
+fn render_worst(html: &mut String, stats: &RunStats) {
+    for (metric, top) in stats.regressions.iter() {
+        let _ = write!(
+            html,
+            "<h2>Worst {} regressions</h2><table><tr><th>index</th><th>path</th><th>pair</th>\
+             <th>inAmount</th><th>delta</th><th>regression</th><th>replay</th></tr>",
+            metric.name()
+        );
+        for regression in top.items.iter() {
+            let request = &regression.request;
+            let _ = write!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{} &rarr; {}</td><td>{}</td><td>{}</td><td>{:.4}%</td>\
+                 <td><a href=\"{}\">old</a> <a href=\"{}\">new</a></td></tr>",
+                regression.index,
+                regression.path_index,
+                escape(&request.from_token),
+                escape(&request.to_token),
+                escape(&request.in_amount),
+                regression.delta,
+                regression.regression_per * 100.0,
+                escape(&regression.old_url),
+                escape(&regression.new_url)
+            );
+        }
+        html.push_str("</table>");
+    }
+}
+
+fn render_pairs(html: &mut String, title: &str, pairs: &[&PairStats]) {
+    let _ = write!(
+        html,
+        "<h2>{}</h2><table><tr><th>pair</th><th>addresses</th><th>compared</th><th>failed</th>\
+         <th>same path</th><th>mean amount regression</th><th>amount diff (all paths)</th></tr>",
+        escape(title)
+    );
+    for pair in pairs {
+        let chart = pair
+            .all_path_diffs
+            .by_metric
+            .get(&Metric::Amount)
+            .and_then(|per_path| per_path.first())
+            .map_or(String::new(), |histogram| bar_chart(&histogram.labels(), &histogram.percentages(), "%"));
+        let _ = write!(
+            html,
+            "<tr><td>{} &rarr; {}</td><td>{} &rarr; {}</td><td>{}</td><td>{}</td><td>{:.2}%</td><td>{:.4}%</td><td>{}</td></tr>",
+            escape(&pair.from_token),
+            escape(&pair.to_token),
+            escape(&pair.from_token_addr),
+            escape(&pair.to_token_addr),
+            pair.compared,
+            pair.failed,
+            pair.same_path_rate() * 100.0,
+            pair.mean_amount_regression * 100.0,
+            chart
+        );
+    }
+    html.push_str("</table>");
+}
+
+fn render_latency(html: &mut String, stats: &RunStats) {
+    html.push_str(
+        "<h2>Latency (ms)</h2><table><tr><th>endpoint</th><th>paths</th><th>count</th><th>min</th><th>mean</th>\
+         <th>p50</th><th>p90</th><th>p99</th><th>max</th></tr>",
+    );
+    let endpoints: [(&str, &EndpointLatency); 2] = [("old", &stats.latency.old), ("new", &stats.latency.new)];
+    for (tag, endpoint) in endpoints {
+        latency_row(html, tag, "all", Summary::from_samples(&endpoint.costs));
+        for (paths, costs) in endpoint.costs_by_paths.iter() {
+            latency_row(html, tag, &paths.to_string(), Summary::from_samples(costs));
+        }
+    }
+    html.push_str("</table><div class=\"charts\">");
+    for (tag, endpoint) in endpoints {
+        let summary = Summary::from_samples(&endpoint.costs).unwrap_or_default();
+        let labels = ["p50", "p90", "p99", "max"].map(|label| label.to_owned());
+        let _ = write!(
+            html,
+            "<div class=\"chart\"><h4>{} router</h4>{}</div>",
+            tag,
+            bar_chart(&labels, &[summary.p50, summary.p90, summary.p99, summary.max], " ms")
+        );
+    }
+    html.push_str("</div>");
+}
+
+fn latency_row(html: &mut String, tag: &str, paths: &str, summary: Option<Summary>) {
+    let summary = summary.unwrap_or_default();
+    let _ = write!(
+        html,
+        "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.2}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+        tag, paths, summary.count, summary.min, summary.mean, summary.p50, summary.p90, summary.p99, summary.max
+    );
+}
+
+fn render_errors(html: &mut String, stats: &RunStats) {
+    html.push_str("<h2>Router errors</h2><table><tr><th>class</th><th>old</th><th>new</th></tr>");
+    for kind in RouterError::KINDS {
+        let count = |classes: &BTreeMap<&'static str, ErrorClass>| {
+            classes.get(kind).map_or(0, |class| class.count)
+        };
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            kind,
+            count(&stats.errors.old),
+            count(&stats.errors.new)
+        );
+    }
+    html.push_str("</table>");
+}
+
+/// Horizontal bar chart, bars are scaled to the largest value.
+fn bar_chart(labels: &[String], values: &[f64], unit: &str) -> String {
+    let max = values.iter().cloned().fold(0.0, f64::max);
+    let height = labels.len() * BAR_HEIGHT;
+    let width = LABEL_WIDTH as f64 + BAR_WIDTH + 80.0;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-size=\"11\">",
+        width, height
+    );
+    for (i, (label, value)) in labels.iter().zip(values).enumerate() {
+        let y = i * BAR_HEIGHT;
+        let bar = if max > 0.0 { value / max * BAR_WIDTH } else { 0.0 };
+        let _ = write!(
+            svg,
+            "<text x=\"0\" y=\"{}\">{}</text><rect x=\"{}\" y=\"{}\" width=\"{:.1}\" height=\"{}\" fill=\"#4a7\"/>\
+             <text x=\"{:.1}\" y=\"{}\">{:.2}{}</text>",
+            y + 13,
+            escape(label),
+            LABEL_WIDTH,
+            y + 3,
+            bar,
+            BAR_HEIGHT - 5,
+            LABEL_WIDTH as f64 + bar + 4.0,
+            y + 13,
+            value,
+            unit
+        );
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_markup() {
+        assert_eq!(escape("<a href='x'>&\"</a>"), "&lt;a href=&#39;x&#39;&gt;&amp;&quot;&lt;/a&gt;");
+    }
+}
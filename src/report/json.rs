@@ -0,0 +1,67 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use serde::Serialize;
+
+use crate::stats::RunStats;
+use crate::types::{Config, RequestCompare, RouterResult};
+use super::RunMetadata;
+
+/// The whole run as one JSON document.
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    #[serde(rename = "config")]
+    config: &'a Config,
+    #[serde(rename = "run")]
+    run: &'a RunMetadata,
+    #[serde(rename = "stats")]
+    stats: &'a RunStats,
+    #[serde(rename = "results")]
+    results: &'a [RequestCompare],
+}
+
+/// One line of the JSON Lines detail output.
+#[derive(Serialize)]
+struct RequestDetail<'a> {
+    #[serde(flatten)]
+    compare: &'a RequestCompare,
+    #[serde(rename = "old")]
+    old: &'a RouterResult,
+    #[serde(rename = "new")]
+    new: &'a RouterResult,
+}
+
+pub fn write_json_report(
+    path: &str,
+    config: &Config,
+    run: &RunMetadata,
+    stats: &RunStats,
+    results: &[RequestCompare],
+) -> anyhow::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, &JsonReport { config, run, stats, results })?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Appends one JSON object per compared request.
+pub struct JsonLinesWriter {
+    writer: BufWriter<File>,
+}
+
+impl JsonLinesWriter {
+    pub fn open(path: &str) -> anyhow::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { writer: BufWriter::new(file) })
+    }
+
+    pub fn write(&mut self, compare: &RequestCompare, old: &RouterResult, new: &RouterResult) -> anyhow::Result<()> {
+        serde_json::to_writer(&mut self.writer, &RequestDetail { compare, old, new })?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
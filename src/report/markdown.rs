@@ -0,0 +1,167 @@
+use std::fmt::Write;
+
+use crate::stats::{DiffDistributions, DiffHistograms, PairStats, RunStats};
+use crate::types::Config;
+use super::{check_thresholds, RunMetadata};
+
+/// Renders the aggregate results as Markdown tables meant to be pasted into release notes.
+pub fn render_markdown(config: &Config, run: &RunMetadata, stats: &RunStats) -> String {
+    let mut md = String::new();
+    let checks = check_thresholds(&config.thresholds, stats);
+    let verdict = if checks.is_empty() {
+        "no thresholds configured"
+    } else if checks.iter().all(|check| check.passed) {
+        "PASS"
+    } else {
+        "FAIL"
+    };
+
+    let _ = writeln!(md, "## Router comparison: **{}**\n", verdict);
+    let _ = writeln!(md, "| | |\n|---|---|");
+    let _ = writeln!(md, "| Old router | {} |", cell(&config.old_url));
+    let _ = writeln!(md, "| New router | {} |", cell(&config.new_url));
+    let _ = writeln!(md, "| Run | {} ({} ms) |", run.started_at, run.duration_ms);
+    let _ = writeln!(md, "| Compared requests | {} |", stats.compared);
+    let _ = writeln!(md, "| Failed requests | {} |", stats.failed);
+    let _ = writeln!(md, "| Undecodable lines | {} |\n", stats.undecodable);
+
+    if !checks.is_empty() {
+        let _ = writeln!(md, "### Thresholds\n\n| Check | Limit | Actual | Result |\n|---|---:|---:|---|");
+        for check in checks.iter() {
+            let _ = writeln!(
+                md,
+                "| {} | {:.4}% | {:.4}% | {} |",
+                check.name,
+                check.limit * 100.0,
+                check.actual * 100.0,
+                if check.passed { "pass" } else { "**fail**" }
+            );
+        }
+        md.push('\n');
+    }
+
+    write_diffs(&mut md, "Diffs of paths with the same pool and road", &stats.same_path_diffs);
+    write_diffs(&mut md, "Diffs of all paths", &stats.all_path_diffs);
+    write_distributions(&mut md, "Diff percentiles of paths with the same pool and road", &stats.same_path_distributions);
+    write_distributions(&mut md, "Diff percentiles of all paths", &stats.all_path_distributions);
+
+    let _ = writeln!(
+        md,
+        "### New vs old amount (tie within {}%)\n\n| Path | Count | Same path | New better | Old better | Tie | outUsd net |\n|---:|---:|---:|---:|---:|---:|---:|",
+        config.tie_tolerance * 100.0
+    );
+    for (i, tally) in stats.win_loss.iter().enumerate() {
+        let count = tally.count().max(1) as f64;
+        let same = stats.paths.equality.get(i).map_or(0.0, |equality| {
+            equality.same as f64 / equality.count().max(1) as f64 * 100.0
+        });
+        let _ = writeln!(
+            md,
+            "| {} | {} | {:.2}% | {:.2}% | {:.2}% | {:.2}% | {} |",
+            i,
+            tally.count(),
+            same,
+            tally.new_better as f64 / count * 100.0,
+            tally.old_better as f64 / count * 100.0,
+            tally.tie as f64 / count * 100.0,
+            tally.usd_net()
+        );
+    }
+    md.push('\n');
+
+    for (metric, top) in stats.regressions.iter() {
+        let _ = writeln!(
+            md,
+            "### Top {} regressions\n\n| Index | Path | From | To | fromTokenAddr | toTokenAddr | inAmount | Decimals | Delta | Regression |\n|---:|---:|---|---|---|---|---:|---|---:|---:|",
+            metric.name()
+        );
+        for regression in top.items.iter() {
+            let request = &regression.request;
+            let _ = writeln!(
+                md,
+                "| {} | {} | {} | {} | {} | {} | {} | {}/{} | {} | {:.4}% |",
+                regression.index,
+                regression.path_index,
+                cell(&request.from_token),
+                cell(&request.to_token),
+                cell(&request.from_token_addr),
+                cell(&request.to_token_addr),
+                cell(&request.in_amount),
+                request.from_decimal,
+                request.to_decimal,
+                regression.delta,
+                regression.regression_per * 100.0
+            );
+        }
+        md.push('\n');
+    }
+
+    write_pairs(&mut md, "Token pairs", &stats.pairs_by_regression());
+    write_pairs(&mut md, "Token symbol pairs", &stats.symbol_pairs_by_regression());
+    md
+}
+
+fn write_pairs(md: &mut String, title: &str, pairs: &[&PairStats]) {
+    let _ = writeln!(
+        md,
+        "### {} (worst mean amount regression first)\n\n| From | To | fromTokenAddr | toTokenAddr | Compared | Failed | Same path | Mean amount regression |\n|---|---|---|---|---:|---:|---:|---:|",
+        title
+    );
+    for pair in pairs {
+        let _ = writeln!(
+            md,
+            "| {} | {} | {} | {} | {} | {} | {:.2}% | {:.4}% |",
+            cell(&pair.from_token),
+            cell(&pair.to_token),
+            cell(&pair.from_token_addr),
+            cell(&pair.to_token_addr),
+            pair.compared,
+            pair.failed,
+            pair.same_path_rate() * 100.0,
+            pair.mean_amount_regression * 100.0
+        );
+    }
+    md.push('\n');
+}
+
+fn write_diffs(md: &mut String, title: &str, histograms: &DiffHistograms) {
+    let _ = writeln!(md, "### {}\n", title);
+    for (metric, per_path) in histograms.by_metric.iter() {
+        let labels = match per_path.first() {
+            Some(histogram) => histogram.labels(),
+            None => continue,
+        };
+        let _ = writeln!(md, "**{}**\n", metric.name());
+        let _ = writeln!(md, "| Path | Count | {} |", labels.join(" | "));
+        let _ = writeln!(md, "|---:|---:|{}", "---:|".repeat(labels.len()));
+        for (i, histogram) in per_path.iter().enumerate() {
+            let percentages: Vec<String> = histogram.percentages().iter().map(|per| format!("{:.2}%", per)).collect();
+            let _ = writeln!(md, "| {} | {} | {} |", i, histogram.count(), percentages.join(" | "));
+        }
+        md.push('\n');
+    }
+}
+
+fn write_distributions(md: &mut String, title: &str, distributions: &DiffDistributions) {
+    let _ = writeln!(
+        md,
+        "### {}\n\n| Metric | Path | Count | Mean | Std | p50 | p90 | p99 | p99.9 | Max |\n|---|---:|---:|---:|---:|---:|---:|---:|---:|---:|",
+        title
+    );
+    for (metric, per_path) in distributions.summaries() {
+        for (i, summary) in per_path.into_iter().enumerate() {
+            let summary = summary.unwrap_or_default();
+            let values: Vec<String> = [summary.mean, summary.std_dev, summary.p50, summary.p90, summary.p99, summary.p999, summary.max]
+                .iter()
+                .map(|value| format!("{:.4}%", value * 100.0))
+                .collect();
+            let _ = writeln!(md, "| {} | {} | {} | {} |", metric.name(), i, summary.count, values.join(" | "));
+        }
+    }
+    md.push('\n');
+}
+
+/// Keeps user supplied text from breaking the table layout.
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
@@ -0,0 +1,129 @@
+use serde::Serialize;
+
+use crate::stats::RunStats;
+use crate::types::{Metric, Thresholds};
+
+pub mod csv;
+pub mod html;
+pub mod json;
+pub mod markdown;
+pub mod regressions;
+
+/// When the run happened, stored next to the results so reports can be traced back.
+#[derive(Clone, Debug, Serialize)]
+pub struct RunMetadata {
+    #[serde(rename = "version")]
+    pub version: &'static str,
+    #[serde(rename = "startedAt")]
+    pub started_at: String,
+    #[serde(rename = "finishedAt")]
+    pub finished_at: String,
+    #[serde(rename = "durationMs")]
+    pub duration_ms: i64,
+}
+
+impl RunMetadata {
+    pub fn new(started_at: chrono::DateTime<chrono::Utc>) -> Self {
+        let finished_at = chrono::Utc::now();
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            started_at: started_at.to_rfc3339(),
+            finished_at: finished_at.to_rfc3339(),
+            duration_ms: (finished_at - started_at).num_milliseconds(),
+        }
+    }
+}
+
+/// Result of checking one configured threshold.
+#[derive(Clone, Debug, Serialize)]
+pub struct Check {
+    #[serde(rename = "name")]
+    pub name: &'static str,
+    #[serde(rename = "limit")]
+    pub limit: f64,
+    #[serde(rename = "actual")]
+    pub actual: f64,
+    #[serde(rename = "passed")]
+    pub passed: bool,
+}
+
+/// Checks every configured threshold against the aggregated results, first paths stand for
+/// the quote users actually get.
+pub fn check_thresholds(thresholds: &Thresholds, stats: &RunStats) -> Vec<Check> {
+    let ratio = |part: u64, whole: u64| if whole == 0 { 0.0 } else { part as f64 / whole as f64 };
+    let first_win_loss = stats.win_loss.first().cloned().unwrap_or_default();
+    let first_equality = stats.paths.equality.first().cloned().unwrap_or_default();
+    let worst_regression = stats.max_regressions.get(&Metric::Amount).map_or(0.0, |worst| worst.max(0.0));
+
+    let mut checks = vec![];
+    if let Some(limit) = thresholds.max_failed_rate {
+        let actual = ratio(stats.failed, stats.failed + stats.compared);
+        checks.push(Check { name: "failed rate", limit, actual, passed: actual <= limit });
+    }
+    if let Some(limit) = thresholds.max_old_better_rate {
+        let actual = ratio(first_win_loss.old_better, first_win_loss.count());
+        checks.push(Check { name: "old better rate", limit, actual, passed: actual <= limit });
+    }
+    if let Some(limit) = thresholds.max_amount_regression {
+        checks.push(Check {
+            name: "worst amount regression",
+            limit,
+            actual: worst_regression,
+            passed: worst_regression <= limit,
+        });
+    }
+    if let Some(limit) = thresholds.min_same_path_rate {
+        let actual = ratio(first_equality.same, first_equality.count());
+        checks.push(Check { name: "same path rate", limit, actual, passed: actual >= limit });
+    }
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::test_support;
+    use crate::types::{CompareResult, Config, RequestCompare, RouterResult};
+
+    fn result(amount: &str) -> RouterResult {
+        serde_json::from_value(json!({ "code": 0, "message": "ok", "data": [{ "amount": amount, "pool": ["v2"] }] })).unwrap()
+    }
+
+    /// Stats of one request the new router quotes 1% less for.
+    fn regressed_stats(thresholds: serde_json::Value) -> (Config, RunStats) {
+        let config = test_support::config(json!({ "maxCount": 1, "topRegressions": 0, "thresholds": thresholds }));
+        let (old, new) = (result("100"), result("99"));
+        let request = RequestCompare {
+            index: 0,
+            request_time: None,
+            request: test_support::request("TRX", "USDT", "1000000"),
+            timing: Default::default(),
+            compares: CompareResult::gen_from_results(&old, &new, None),
+        };
+        let mut stats = RunStats::new(&config);
+        stats.record_success(&request, &old, &new);
+        (config, stats)
+    }
+
+    #[test]
+    fn amount_regression_is_checked_without_top_regressions() {
+        let (config, stats) = regressed_stats(json!({ "maxAmountRegression": 0.005, "maxFailedRate": 0.1 }));
+        assert!(stats.regressions[&Metric::Amount].items.is_empty());
+        let checks = check_thresholds(&config.thresholds, &stats);
+        assert_eq!(checks.len(), 2);
+        let regression = checks.iter().find(|check| check.name == "worst amount regression").unwrap();
+        assert!((regression.actual - 0.01).abs() < 1e-9);
+        assert!(!regression.passed);
+        assert!(markdown::render_markdown(&config, &RunMetadata::new(chrono::Utc::now()), &stats).contains("**FAIL**"));
+    }
+
+    #[test]
+    fn verdict_needs_configured_thresholds() {
+        let (config, stats) = regressed_stats(json!({}));
+        assert!(check_thresholds(&config.thresholds, &stats).is_empty());
+        let md = markdown::render_markdown(&config, &RunMetadata::new(chrono::Utc::now()), &stats);
+        assert!(md.contains("no thresholds configured"));
+        assert!(!md.contains("PASS"));
+    }
+}
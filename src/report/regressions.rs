@@ -0,0 +1,29 @@
+use std::fmt::Write;
+
+use crate::stats::RunStats;
+
+/// Ranked list of the worst regressions per metric, each with the request, both paths and
+/// curl lines that replay it against the old and new router.
+pub fn render_regressions(stats: &RunStats) -> String {
+    let mut text = String::new();
+    for (metric, top) in stats.regressions.iter() {
+        let _ = writeln!(text, "-------------------Top {} regressions-----------------------------", metric.name());
+        for (rank, regression) in top.items.iter().enumerate() {
+            let _ = writeln!(
+                text,
+                "#{} index:{} path_index:{} regression:{}% delta:{}",
+                rank + 1,
+                regression.index,
+                regression.path_index,
+                regression.regression_per * 100.0,
+                regression.delta
+            );
+            let _ = writeln!(text, "request:{}", serde_json::to_string(&regression.request).unwrap_or_default());
+            let _ = writeln!(text, "old:{}", serde_json::to_string(&regression.old).unwrap_or_default());
+            let _ = writeln!(text, "new:{}", serde_json::to_string(&regression.new).unwrap_or_default());
+            let _ = writeln!(text, "curl -s '{}'", regression.old_url);
+            let _ = writeln!(text, "curl -s '{}'", regression.new_url);
+        }
+    }
+    text
+}
@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+use log::info;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::input::LogEntry;
+use crate::types::{AmountQuota, LogContent, Sampling};
+
+/// Uniform sample (algorithm R) of the entries offered to it, tagged with their position
+/// in the log.
+struct Reservoir {
+    capacity: usize,
+    offered: u64,
+    items: Vec<(u64, LogEntry)>,
+}
+
+impl Reservoir {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            offered: 0,
+            items: vec![],
+        }
+    }
+
+    fn offer(&mut self, position: u64, entry: LogEntry, rng: &mut StdRng) {
+        self.offered += 1;
+        if self.items.len() < self.capacity {
+            self.items.push((position, entry));
+            return;
+        }
+        let slot = rng.gen_range(0..self.offered);
+        if slot < self.capacity as u64 {
+            self.items[slot as usize] = (position, entry);
+        }
+    }
+}
+
+/// Reads every entry and returns the sample selected by `sampling` with `seed`, in log order
+/// and capped at `max_count` entries. `Sampling::First` returns the first `max_count` entries.
+pub fn sample(
+    entries: impl Iterator<Item = anyhow::Result<LogEntry>>,
+    sampling: &Sampling,
+    max_count: u64,
+    seed: u64,
+) -> anyhow::Result<Vec<LogEntry>> {
+    let max_count = usize::try_from(max_count).unwrap_or(usize::MAX);
+    if let Sampling::First = sampling {
+        return entries.take(max_count).collect();
+    }
+    info!("Sampling {:?} with seed {}", sampling, seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let mut strata: BTreeMap<String, Reservoir> = BTreeMap::new();
+    for (position, entry) in entries.enumerate() {
+        let entry = entry?;
+        let log_content = &entry.content;
+        let (key, capacity) = match sampling {
+            Sampling::First => unreachable!(),
+            Sampling::Reservoir { .. } => (String::new(), max_count),
+            Sampling::StratifiedPair { per_pair, .. } => {
+                (format!("{}_{}", log_content.from_token, log_content.to_token), *per_pair)
+            }
+            Sampling::AmountQuotas { quotas, .. } => match quota_of(quotas, log_content) {
+                Some((i, quota)) => (i.to_string(), quota.count),
+                None => continue,
+            },
+        };
+        strata
+            .entry(key)
+            .or_insert_with(|| Reservoir::new(capacity))
+            .offer(position as u64, entry, &mut rng);
+    }
+
+    let mut sampled: Vec<(u64, LogEntry)> = strata.into_values().flat_map(|reservoir| reservoir.items).collect();
+    if sampled.len() > max_count {
+        let mut kept = rand::seq::index::sample(&mut rng, sampled.len(), max_count).into_vec();
+        kept.sort_unstable();
+        sampled = kept.into_iter().map(|i| sampled[i].clone()).collect();
+    }
+    sampled.sort_by_key(|(position, _)| *position);
+    Ok(sampled.into_iter().map(|(_, entry)| entry).collect())
+}
+
+/// Index and quota of the first range containing the amount of `log_content`.
+fn quota_of<'a>(quotas: &'a [AmountQuota], log_content: &LogContent) -> Option<(usize, &'a AmountQuota)> {
+    let amount = log_content.amount()?;
+    quotas
+        .iter()
+        .enumerate()
+        .find(|(_, quota)| quota.max_amount.is_none_or(|max_amount| amount < max_amount))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    /// `in_amount` whole `from_token`.
+    fn log_entry(from_token: &str, in_amount: u64) -> anyhow::Result<LogEntry> {
+        let content = LogContent { from_decimal: 0, ..test_support::request(from_token, "USDT", &in_amount.to_string()) };
+        Ok(LogEntry { content, time: None, position: None })
+    }
+
+    #[test]
+    fn seeded_samples_are_reproducible_and_in_log_order() {
+        let entries = || (1..=1000).map(|i| log_entry(if i % 10 == 0 { "SUN" } else { "TRX" }, i));
+        let amounts = |sampled: Vec<LogEntry>| -> Vec<u64> {
+            sampled.iter().map(|entry| entry.content.in_amount.parse().unwrap()).collect()
+        };
+
+        let reservoir = Sampling::Reservoir { seed: None };
+        let first = amounts(sample(entries(), &reservoir, 20, 7).unwrap());
+        assert_eq!(first.len(), 20);
+        assert!(first.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(first, amounts(sample(entries(), &reservoir, 20, 7).unwrap()));
+
+        let stratified = Sampling::StratifiedPair { per_pair: 5, seed: None };
+        let sampled = sample(entries(), &stratified, 100, 7).unwrap();
+        assert_eq!(sampled.iter().filter(|entry| entry.content.from_token == "SUN").count(), 5);
+        assert_eq!(sampled.len(), 10);
+
+        let quotas = Sampling::AmountQuotas {
+            quotas: vec![
+                AmountQuota { max_amount: Some(10.0), count: 3 },
+                AmountQuota { max_amount: None, count: 2 },
+            ],
+            seed: None,
+        };
+        let sampled = amounts(sample(entries(), &quotas, 100, 7).unwrap());
+        assert_eq!(sampled.iter().filter(|amount| **amount < 10).count(), 3);
+        assert_eq!(sampled.len(), 5);
+    }
+}
@@ -0,0 +1,940 @@
+use std::collections::BTreeMap;
+use bigdecimal::{BigDecimal, Signed};
+use reqwest::Url;
+use serde::{Serialize, Serializer};
+use serde::ser::SerializeStruct;
+
+use crate::client::client::request_url;
+use crate::client::error::RouterFailure;
+use crate::types::{
+    CompareResult, Config, DiffBuckets, FieldOutcome, LogContent, Metric, Path, RequestCompare, RouterResult,
+    RouterTiming, Side,
+};
+
+/// Requests kept per error class as examples in the report.
+const MAX_ERROR_SAMPLES: usize = 3;
+
+/// Order statistics of a set of samples, percentiles use the nearest-rank method.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Summary {
+    pub count: usize,
+    pub min: f64,
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub p999: f64,
+    pub max: f64,
+    /// Population standard deviation.
+    #[serde(rename = "stdDev")]
+    pub std_dev: f64,
+}
+
+impl Summary {
+    pub fn from_samples(samples: &[f64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let count = sorted.len();
+        let mean = sorted.iter().sum::<f64>() / count as f64;
+        let variance = sorted.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / count as f64;
+        Some(Self {
+            count,
+            min: sorted[0],
+            mean,
+            p50: percentile(&sorted, 0.5),
+            p90: percentile(&sorted, 0.9),
+            p99: percentile(&sorted, 0.99),
+            p999: percentile(&sorted, 0.999),
+            max: sorted[count - 1],
+            std_dev: variance.sqrt(),
+        })
+    }
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "count:{}, min:{}, mean:{:.2}, p50:{}, p90:{}, p99:{}, max:{}",
+            self.count, self.min, self.mean, self.p50, self.p90, self.p99, self.max
+        )
+    }
+}
+
+/// `sorted` must be non-empty and sorted ascending.
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Latency samples (millis, retries included) of one router endpoint, serialized as summaries.
+/// Failed calls count in `costs` and the retries but not per path count.
+#[derive(Clone, Debug, Default)]
+pub struct EndpointLatency {
+    pub costs: Vec<f64>,
+    pub costs_by_paths: BTreeMap<usize, Vec<f64>>,
+    pub retries: u64,
+    pub retried_requests: u64,
+}
+
+impl EndpointLatency {
+    fn record(&mut self, cost: i64, retries: u32, path_count: Option<usize>) {
+        self.costs.push(cost as f64);
+        if let Some(path_count) = path_count {
+            self.costs_by_paths.entry(path_count).or_default().push(cost as f64);
+        }
+        self.retries += retries as u64;
+        if retries > 0 {
+            self.retried_requests += 1;
+        }
+    }
+}
+
+impl Serialize for EndpointLatency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let by_paths: BTreeMap<usize, Option<Summary>> = self.costs_by_paths
+            .iter()
+            .map(|(paths, costs)| (*paths, Summary::from_samples(costs)))
+            .collect();
+        let mut state = serializer.serialize_struct("EndpointLatency", 4)?;
+        state.serialize_field("summary", &Summary::from_samples(&self.costs))?;
+        state.serialize_field("byPathCount", &by_paths)?;
+        state.serialize_field("retries", &self.retries)?;
+        state.serialize_field("retriedRequests", &self.retried_requests)?;
+        state.end()
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct LatencyStats {
+    pub old: EndpointLatency,
+    pub new: EndpointLatency,
+}
+
+impl LatencyStats {
+    pub fn record(&mut self, timing: &RouterTiming, old_paths: usize, new_paths: usize) {
+        self.old.record(timing.old_cost, timing.old_retries, Some(old_paths));
+        self.new.record(timing.new_cost, timing.new_retries, Some(new_paths));
+    }
+
+    /// Timeouts and calls that failed after retrying are often the slowest, leaving them out
+    /// would flatter the endpoint.
+    pub fn record_failure(&mut self, timing: &RouterTiming) {
+        self.old.record(timing.old_cost, timing.old_retries, None);
+        self.new.record(timing.new_cost, timing.new_retries, None);
+    }
+}
+
+/// Counts of values per bucket; bucket `i` holds values in `[edges[i - 1], edges[i])`, the
+/// first bucket is open below and the last one open above.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    edges: Vec<f64>,
+    counts: Vec<u64>,
+}
+
+impl Histogram {
+    pub fn new(edges: &[f64]) -> Self {
+        let mut edges = edges.to_vec();
+        edges.sort_by(|a, b| a.total_cmp(b));
+        edges.dedup();
+        Self {
+            counts: vec![0; edges.len() + 1],
+            edges,
+        }
+    }
+
+    pub fn record(&mut self, value: f64) {
+        let index = self.edges.partition_point(|edge| *edge <= value);
+        self.counts[index] += 1;
+    }
+
+    pub fn edges(&self) -> &[f64] {
+        &self.edges
+    }
+
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Human readable range of each bucket, edges rendered as percentages.
+    pub fn labels(&self) -> Vec<String> {
+        let percent = |edge: f64| format!("{}%", (edge * 100.0 * 1e6).round() / 1e6);
+        if self.edges.is_empty() {
+            return vec!["all".to_owned()];
+        }
+        let mut labels = vec![format!("<{}", percent(self.edges[0]))];
+        for pair in self.edges.windows(2) {
+            labels.push(format!("{}~{}", percent(pair[0]), percent(pair[1])));
+        }
+        labels.push(format!(">={}", percent(self.edges[self.edges.len() - 1])));
+        labels
+    }
+
+    /// Share of each bucket in percent, all zero when the histogram is empty.
+    pub fn percentages(&self) -> Vec<f64> {
+        let count = self.count().max(1) as f64;
+        self.counts.iter().map(|c| *c as f64 / count * 100.0).collect()
+    }
+}
+
+impl Serialize for Histogram {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Histogram", 4)?;
+        state.serialize_field("count", &self.count())?;
+        state.serialize_field("edges", &self.edges)?;
+        state.serialize_field("labels", &self.labels())?;
+        state.serialize_field("counts", &self.counts)?;
+        state.end()
+    }
+}
+
+impl std::fmt::Display for Histogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "count:{}", self.count())?;
+        for (label, per) in self.labels().iter().zip(self.percentages()) {
+            write!(f, ", {}:{}%", label, per)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the item at `index`, growing `items` with defaults when it is too short.
+pub(crate) fn grow_to<T: Default>(items: &mut Vec<T>, index: usize) -> &mut T {
+    if items.len() <= index {
+        items.resize_with(index + 1, T::default);
+    }
+    &mut items[index]
+}
+
+/// Diff histograms of every metric for each path index, grown as deeper paths show up.
+#[derive(Clone, Debug, Serialize)]
+#[serde(transparent)]
+pub struct DiffHistograms {
+    #[serde(skip)]
+    buckets: DiffBuckets,
+    pub by_metric: BTreeMap<Metric, Vec<Histogram>>,
+}
+
+impl DiffHistograms {
+    pub fn new(buckets: &DiffBuckets) -> Self {
+        let by_metric = Metric::ALL.iter().map(|metric| (*metric, vec![])).collect();
+        Self {
+            buckets: buckets.clone(),
+            by_metric,
+        }
+    }
+
+    /// Metrics that could not be compared are left out.
+    pub fn record(&mut self, path_index: usize, compare: &CompareResult) {
+        for (metric, histograms) in self.by_metric.iter_mut() {
+            while histograms.len() <= path_index {
+                histograms.push(Histogram::new(self.buckets.edges(*metric)));
+            }
+            if let Some(diff_per) = compare.diff_per(*metric) {
+                histograms[path_index].record(diff_per);
+            }
+        }
+    }
+}
+
+/// Exact diff samples of every metric for each path index, serialized as summaries.
+#[derive(Clone, Debug)]
+pub struct DiffDistributions {
+    pub by_metric: BTreeMap<Metric, Vec<Vec<f64>>>,
+}
+
+impl Default for DiffDistributions {
+    fn default() -> Self {
+        Self {
+            by_metric: Metric::ALL.iter().map(|metric| (*metric, vec![])).collect(),
+        }
+    }
+}
+
+impl DiffDistributions {
+    /// Metrics that could not be compared, or whose relative diff is undefined, are left out.
+    pub fn record(&mut self, path_index: usize, compare: &CompareResult) {
+        for (metric, samples) in self.by_metric.iter_mut() {
+            let samples = grow_to(samples, path_index);
+            if let Some(diff_per) = compare.diff_per(*metric).filter(|diff_per| diff_per.is_finite()) {
+                samples.push(diff_per);
+            }
+        }
+    }
+
+    pub fn summaries(&self) -> BTreeMap<Metric, Vec<Option<Summary>>> {
+        self.by_metric
+            .iter()
+            .map(|(metric, per_path)| {
+                (*metric, per_path.iter().map(|samples| Summary::from_samples(samples)).collect())
+            })
+            .collect()
+    }
+}
+
+impl Serialize for DiffDistributions {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.summaries().serialize(serializer)
+    }
+}
+
+/// How often pool and road agree for one path index.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct PathEquality {
+    pub same: u64,
+    pub differ: u64,
+}
+
+impl PathEquality {
+    pub fn count(&self) -> u64 {
+        self.same + self.differ
+    }
+}
+
+/// Number of paths returned by each router and pool/road agreement per path index.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct PathStats {
+    #[serde(rename = "equality")]
+    pub equality: Vec<PathEquality>,
+    /// Requests per `(old path count, new path count)`.
+    #[serde(rename = "pathCounts", serialize_with = "serialize_path_counts")]
+    pub path_counts: BTreeMap<(usize, usize), u64>,
+}
+
+fn serialize_path_counts<S: Serializer>(
+    path_counts: &BTreeMap<(usize, usize), u64>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct Entry {
+        old: usize,
+        new: usize,
+        count: u64,
+    }
+    serializer.collect_seq(path_counts.iter().map(|((old, new), count)| Entry {
+        old: *old,
+        new: *new,
+        count: *count,
+    }))
+}
+
+impl PathStats {
+    pub fn record_counts(&mut self, old_paths: usize, new_paths: usize) {
+        *self.path_counts.entry((old_paths, new_paths)).or_default() += 1;
+    }
+
+    pub fn record_path(&mut self, path_index: usize, compare: &CompareResult) {
+        let equality = grow_to(&mut self.equality, path_index);
+        if compare.pool_eq && compare.road_addr_eq {
+            equality.same += 1;
+        } else {
+            equality.differ += 1;
+        }
+    }
+
+    /// `(requests, same count, old returned more, new returned more)`.
+    pub fn count_mismatch(&self) -> (u64, u64, u64, u64) {
+        let mut res = (0, 0, 0, 0);
+        for ((old, new), count) in self.path_counts.iter() {
+            res.0 += count;
+            match old.cmp(new) {
+                std::cmp::Ordering::Equal => res.1 += count,
+                std::cmp::Ordering::Greater => res.2 += count,
+                std::cmp::Ordering::Less => res.3 += count,
+            }
+        }
+        res
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ErrorSample {
+    pub request: String,
+    pub error: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ErrorClass {
+    pub count: u64,
+    /// Up to `MAX_ERROR_SAMPLES` examples.
+    pub samples: Vec<ErrorSample>,
+}
+
+/// Router failures per endpoint, keyed by `RouterError::kind`.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ErrorStats {
+    pub old: BTreeMap<&'static str, ErrorClass>,
+    pub new: BTreeMap<&'static str, ErrorClass>,
+}
+
+impl ErrorStats {
+    pub fn record(&mut self, failure: &RouterFailure, request: &str) {
+        for (classes, error) in [(&mut self.old, &failure.old), (&mut self.new, &failure.new)] {
+            if let Some(error) = error {
+                let class = classes.entry(error.kind()).or_default();
+                class.count += 1;
+                if class.samples.len() < MAX_ERROR_SAMPLES {
+                    class.samples.push(ErrorSample {
+                        request: request.to_owned(),
+                        error: error.to_string(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Win/lose/tie tally of one path index and the USD value the new router gains or loses.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct WinLoss {
+    #[serde(rename = "newBetter")]
+    pub new_better: u64,
+    #[serde(rename = "oldBetter")]
+    pub old_better: u64,
+    #[serde(rename = "tie")]
+    pub tie: u64,
+    /// Sum of positive `outUsd` deltas.
+    #[serde(rename = "usdGained")]
+    pub usd_gained: BigDecimal,
+    /// Sum of negative `outUsd` deltas, as a positive value.
+    #[serde(rename = "usdLost")]
+    pub usd_lost: BigDecimal,
+}
+
+impl WinLoss {
+    pub fn record(&mut self, compare: &CompareResult, tolerance: f64) {
+        match compare.better_side(tolerance) {
+            Some(Side::New) => self.new_better += 1,
+            Some(Side::Old) => self.old_better += 1,
+            Some(Side::Tie) => self.tie += 1,
+            None => {}
+        }
+        if let Some(diff) = compare.diff_outusd.as_ref() {
+            if diff.delta.is_positive() {
+                self.usd_gained += &diff.delta;
+            } else {
+                self.usd_lost += diff.delta.abs();
+            }
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.new_better + self.old_better + self.tie
+    }
+
+    pub fn usd_net(&self) -> BigDecimal {
+        &self.usd_gained - &self.usd_lost
+    }
+}
+
+/// Aggregates of the requests of one token pair, grouped by token addresses or by symbols.
+/// The addresses of a symbol pair read `*` when it spans several.
+#[derive(Clone, Debug, Serialize)]
+pub struct PairStats {
+    #[serde(rename = "fromToken")]
+    pub from_token: String,
+    #[serde(rename = "toToken")]
+    pub to_token: String,
+    #[serde(rename = "fromTokenAddr")]
+    pub from_token_addr: String,
+    #[serde(rename = "toTokenAddr")]
+    pub to_token_addr: String,
+    #[serde(rename = "compared")]
+    pub compared: u64,
+    #[serde(rename = "failed")]
+    pub failed: u64,
+    #[serde(rename = "samePathDiffs")]
+    pub same_path_diffs: DiffHistograms,
+    #[serde(rename = "allPathDiffs")]
+    pub all_path_diffs: DiffHistograms,
+    #[serde(rename = "equality")]
+    pub equality: Vec<PathEquality>,
+    /// Mean of the amount regressions of every compared path, negative when the new router
+    /// quotes more on average.
+    #[serde(rename = "meanAmountRegression")]
+    pub mean_amount_regression: f64,
+    #[serde(skip)]
+    amount_regressions: u64,
+}
+
+impl PairStats {
+    fn new(request: &LogContent, buckets: &DiffBuckets) -> Self {
+        Self {
+            from_token: request.from_token.clone(),
+            to_token: request.to_token.clone(),
+            from_token_addr: request.from_token_addr.clone(),
+            to_token_addr: request.to_token_addr.clone(),
+            compared: 0,
+            failed: 0,
+            same_path_diffs: DiffHistograms::new(buckets),
+            all_path_diffs: DiffHistograms::new(buckets),
+            equality: vec![],
+            mean_amount_regression: 0.0,
+            amount_regressions: 0,
+        }
+    }
+
+    fn note_addresses(&mut self, request: &LogContent) {
+        for (addr, request_addr) in [
+            (&mut self.from_token_addr, &request.from_token_addr),
+            (&mut self.to_token_addr, &request.to_token_addr),
+        ] {
+            if addr != request_addr {
+                *addr = "*".to_owned();
+            }
+        }
+    }
+
+    fn record_path(&mut self, path_index: usize, compare: &CompareResult) {
+        let equality = grow_to(&mut self.equality, path_index);
+        if compare.pool_eq && compare.road_addr_eq {
+            equality.same += 1;
+            self.same_path_diffs.record(path_index, compare);
+        } else {
+            equality.differ += 1;
+        }
+        self.all_path_diffs.record(path_index, compare);
+        if let Some(diff) = compare.diff_amount.as_ref() {
+            let regression_per = -diff.delta_rel_f64();
+            if regression_per.is_finite() {
+                self.amount_regressions += 1;
+                self.mean_amount_regression +=
+                    (regression_per - self.mean_amount_regression) / self.amount_regressions as f64;
+            }
+        }
+    }
+
+    pub fn same_path_rate(&self) -> f64 {
+        let (same, count) = self.equality.iter().fold((0, 0), |(same, count), equality| {
+            (same + equality.same, count + equality.count())
+        });
+        same as f64 / count.max(1) as f64
+    }
+}
+
+/// Pairs ordered worst mean amount regression first, ties keep the address order.
+fn sort_pairs(pairs: &BTreeMap<(String, String), PairStats>) -> Vec<&PairStats> {
+    let mut sorted: Vec<&PairStats> = pairs.values().collect();
+    sorted.sort_by(|a, b| b.mean_amount_regression.total_cmp(&a.mean_amount_regression));
+    sorted
+}
+
+fn serialize_pairs<S: Serializer>(
+    pairs: &BTreeMap<(String, String), PairStats>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(sort_pairs(pairs))
+}
+
+/// A compared path where the new router quoted worse than the old one, with everything
+/// needed to replay it.
+#[derive(Clone, Debug, Serialize)]
+pub struct Regression {
+    #[serde(rename = "index")]
+    pub index: u64,
+    #[serde(rename = "pathIndex")]
+    pub path_index: usize,
+    #[serde(rename = "request")]
+    pub request: LogContent,
+    /// How much worse the new quote is relative to the old one, positive.
+    #[serde(rename = "regressionPer")]
+    pub regression_per: f64,
+    /// `new - old`, exact.
+    #[serde(rename = "delta")]
+    pub delta: BigDecimal,
+    #[serde(rename = "old")]
+    pub old: Path,
+    #[serde(rename = "new")]
+    pub new: Path,
+    #[serde(rename = "oldUrl")]
+    pub old_url: String,
+    #[serde(rename = "newUrl")]
+    pub new_url: String,
+}
+
+/// The `limit` largest regressions of one metric, sorted worst first.
+#[derive(Clone, Debug, Serialize)]
+#[serde(transparent)]
+pub struct TopRegressions {
+    #[serde(skip)]
+    limit: usize,
+    pub items: Vec<Regression>,
+}
+
+impl TopRegressions {
+    pub fn new(limit: usize) -> Self {
+        Self { limit, items: vec![] }
+    }
+
+    /// Keeps `regression` when it ranks among the worst `limit` ones; the regression is only
+    /// built when it does.
+    fn offer(&mut self, regression_per: f64, regression: impl FnOnce() -> Regression) {
+        if self.limit == 0 || regression_per <= 0.0 {
+            return;
+        }
+        if self.items.len() >= self.limit && self.items.last().is_none_or(|last| last.regression_per >= regression_per) {
+            return;
+        }
+        let position = self.items.partition_point(|item| item.regression_per >= regression_per);
+        self.items.insert(position, regression());
+        self.items.truncate(self.limit);
+    }
+}
+
+/// Router URLs used to build the replay URL of a regression.
+#[derive(Clone, Debug)]
+struct ReplayUrls {
+    old: Option<Url>,
+    new: Option<Url>,
+    use_base_tokens: String,
+}
+
+/// Everything aggregated over a run, the text, JSON and other reports are rendered from it.
+#[derive(Clone, Debug, Serialize)]
+pub struct RunStats {
+    /// Requests for which both routers answered.
+    #[serde(rename = "compared")]
+    pub compared: u64,
+    #[serde(rename = "failed")]
+    pub failed: u64,
+    /// Lines matching the log format that could not be decoded, skipped.
+    #[serde(rename = "undecodable")]
+    pub undecodable: u64,
+    /// Histograms of paths whose pool and road are equal.
+    #[serde(rename = "samePathDiffs")]
+    pub same_path_diffs: DiffHistograms,
+    /// Histograms of every compared path.
+    #[serde(rename = "allPathDiffs")]
+    pub all_path_diffs: DiffHistograms,
+    /// Percentiles of the diffs of paths whose pool and road are equal.
+    #[serde(rename = "samePathDistributions")]
+    pub same_path_distributions: DiffDistributions,
+    /// Percentiles of the diffs of every compared path.
+    #[serde(rename = "allPathDistributions")]
+    pub all_path_distributions: DiffDistributions,
+    #[serde(rename = "paths")]
+    pub paths: PathStats,
+    #[serde(rename = "winLoss")]
+    pub win_loss: Vec<WinLoss>,
+    #[serde(rename = "fieldOutcomes")]
+    pub field_outcomes: BTreeMap<&'static str, BTreeMap<FieldOutcome, u64>>,
+    #[serde(rename = "latency")]
+    pub latency: LatencyStats,
+    #[serde(rename = "errors")]
+    pub errors: ErrorStats,
+    /// Largest regressions of the new router per metric.
+    #[serde(rename = "regressions")]
+    pub regressions: BTreeMap<Metric, TopRegressions>,
+    /// Worst regression per metric over every compared path, kept whatever `topRegressions`
+    /// is; negative when the new router only improved.
+    #[serde(rename = "maxRegressions")]
+    pub max_regressions: BTreeMap<Metric, f64>,
+    /// Per token pair breakdown keyed by `(fromTokenAddr, toTokenAddr)`, serialized worst
+    /// mean amount regression first.
+    #[serde(rename = "pairs", serialize_with = "serialize_pairs")]
+    pub pairs: BTreeMap<(String, String), PairStats>,
+    /// The same breakdown keyed by `(fromToken, toToken)`, the pair dedup and sampling use.
+    #[serde(rename = "symbolPairs", serialize_with = "serialize_pairs")]
+    pub symbol_pairs: BTreeMap<(String, String), PairStats>,
+    #[serde(skip)]
+    buckets: DiffBuckets,
+    #[serde(skip)]
+    tie_tolerance: f64,
+    #[serde(skip)]
+    replay_urls: ReplayUrls,
+}
+
+impl RunStats {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            compared: 0,
+            failed: 0,
+            undecodable: 0,
+            same_path_diffs: DiffHistograms::new(&config.diff_buckets),
+            all_path_diffs: DiffHistograms::new(&config.diff_buckets),
+            same_path_distributions: DiffDistributions::default(),
+            all_path_distributions: DiffDistributions::default(),
+            paths: PathStats::default(),
+            win_loss: vec![],
+            field_outcomes: BTreeMap::new(),
+            latency: LatencyStats::default(),
+            errors: ErrorStats::default(),
+            regressions: Metric::REGRESSION
+                .iter()
+                .map(|metric| (*metric, TopRegressions::new(config.top_regressions)))
+                .collect(),
+            max_regressions: BTreeMap::new(),
+            pairs: BTreeMap::new(),
+            symbol_pairs: BTreeMap::new(),
+            buckets: config.diff_buckets.clone(),
+            tie_tolerance: config.tie_tolerance,
+            replay_urls: ReplayUrls {
+                old: Url::parse(&config.old_url).ok(),
+                new: Url::parse(&config.new_url).ok(),
+                use_base_tokens: config.use_base_tokens.clone(),
+            },
+        }
+    }
+
+    pub fn record_success(&mut self, request: &RequestCompare, old: &RouterResult, new: &RouterResult) {
+        let old_paths = old.data.as_ref().map_or(0, |paths| paths.len());
+        let new_paths = new.data.as_ref().map_or(0, |paths| paths.len());
+        self.compared += 1;
+        self.latency.record(&request.timing, old_paths, new_paths);
+        self.paths.record_counts(old_paths, new_paths);
+        let old_paths = old.data.as_deref().unwrap_or_default();
+        let new_paths = new.data.as_deref().unwrap_or_default();
+        for pair in self.pairs_of(&request.request) {
+            pair.compared += 1;
+            for (i, compare) in request.compares.iter().enumerate() {
+                pair.record_path(i, compare);
+            }
+        }
+        for (i, compare) in request.compares.iter().enumerate() {
+            self.record_regressions(request, i, &old_paths[i], &new_paths[i]);
+            grow_to(&mut self.win_loss, i).record(compare, self.tie_tolerance);
+            for (field, outcome) in compare.field_outcomes.iter() {
+                *self.field_outcomes.entry(field).or_default().entry(outcome).or_default() += 1;
+            }
+            if compare.pool_eq && compare.road_addr_eq {
+                self.same_path_diffs.record(i, compare);
+                self.same_path_distributions.record(i, compare);
+            }
+            self.paths.record_path(i, compare);
+            self.all_path_diffs.record(i, compare);
+            self.all_path_distributions.record(i, compare);
+        }
+    }
+
+    fn record_regressions(&mut self, request: &RequestCompare, path_index: usize, old: &Path, new: &Path) {
+        let replay_urls = &self.replay_urls;
+        for (metric, top) in self.regressions.iter_mut() {
+            let diff = match request.compares[path_index].diff(*metric) {
+                Some(diff) => diff,
+                None => continue,
+            };
+            let delta_per = diff.delta_rel_f64();
+            let regression_per = if metric.higher_is_better() { -delta_per } else { delta_per };
+            if regression_per.is_finite() {
+                let max = self.max_regressions.entry(*metric).or_insert(regression_per);
+                *max = max.max(regression_per);
+            }
+            top.offer(regression_per, || {
+                let url = |base: &Option<Url>, use_base_tokens: Option<String>| {
+                    base.as_ref().map_or(String::new(), |base| {
+                        request_url(base, &request.request, use_base_tokens).to_string()
+                    })
+                };
+                Regression {
+                    index: request.index,
+                    path_index,
+                    request: request.request.clone(),
+                    regression_per,
+                    delta: diff.delta.clone(),
+                    old: old.clone(),
+                    new: new.clone(),
+                    old_url: url(&replay_urls.old, None),
+                    new_url: url(&replay_urls.new, Some(replay_urls.use_base_tokens.clone())),
+                }
+            });
+        }
+    }
+
+    pub fn record_failure(&mut self, failure: &RouterFailure, request: &LogContent) {
+        self.failed += 1;
+        self.latency.record_failure(&failure.timing);
+        for pair in self.pairs_of(request) {
+            pair.failed += 1;
+        }
+        self.errors.record(failure, &serde_json::to_string(request).unwrap_or_default());
+    }
+
+    pub fn record_undecodable(&mut self) {
+        self.undecodable += 1;
+    }
+
+    /// Address pairs ordered worst mean amount regression first.
+    pub fn pairs_by_regression(&self) -> Vec<&PairStats> {
+        sort_pairs(&self.pairs)
+    }
+
+    /// Symbol pairs ordered worst mean amount regression first.
+    pub fn symbol_pairs_by_regression(&self) -> Vec<&PairStats> {
+        sort_pairs(&self.symbol_pairs)
+    }
+
+    /// The address pair and the symbol pair of `request`.
+    fn pairs_of(&mut self, request: &LogContent) -> [&mut PairStats; 2] {
+        let buckets = &self.buckets;
+        let by_addr = self
+            .pairs
+            .entry((request.from_token_addr.clone(), request.to_token_addr.clone()))
+            .or_insert_with(|| PairStats::new(request, buckets));
+        let by_symbol = self
+            .symbol_pairs
+            .entry((request.from_token.clone(), request.to_token.clone()))
+            .or_insert_with(|| PairStats::new(request, buckets));
+        by_symbol.note_addresses(request);
+        [by_addr, by_symbol]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::error::RouterError;
+    use crate::test_support;
+
+    #[test]
+    fn summary_uses_nearest_rank() {
+        let samples: Vec<f64> = (1..=100).rev().map(|v| v as f64).collect();
+        let summary = Summary::from_samples(&samples).unwrap();
+        assert_eq!(summary.count, 100);
+        assert_eq!(summary.min, 1.0);
+        assert_eq!(summary.p50, 50.0);
+        assert_eq!(summary.p90, 90.0);
+        assert_eq!(summary.p99, 99.0);
+        assert_eq!(summary.p999, 100.0);
+        assert_eq!(summary.max, 100.0);
+        assert_eq!(summary.mean, 50.5);
+        assert!((summary.std_dev - (9999.0f64 / 12.0).sqrt()).abs() < 1e-9);
+        assert!(Summary::from_samples(&[]).is_none());
+    }
+
+    #[test]
+    fn histogram_labels_match_buckets() {
+        let mut histogram = Histogram::new(&[0.001, 0.0001, 0.01]);
+        for value in [0.0, 0.00005, 0.0001, 0.005, 0.01, 1.0, f64::INFINITY] {
+            histogram.record(value);
+        }
+        assert_eq!(histogram.labels(), vec!["<0.01%", "0.01%~0.1%", "0.1%~1%", ">=1%"]);
+        assert_eq!(histogram.counts(), &[2, 1, 1, 3]);
+        assert_eq!(histogram.count(), 7);
+    }
+
+    #[test]
+    fn top_regressions_keep_the_worst_in_order() {
+        let regression = |index: u64, regression_per: f64| {
+            move || Regression {
+                index,
+                path_index: 0,
+                request: test_support::request("TRX", "USDT", "1"),
+                regression_per,
+                delta: BigDecimal::from(0),
+                old: serde_json::from_value(serde_json::json!({})).unwrap(),
+                new: serde_json::from_value(serde_json::json!({})).unwrap(),
+                old_url: String::new(),
+                new_url: String::new(),
+            }
+        };
+        let mut top = TopRegressions::new(3);
+        for (index, regression_per) in [(0, 0.02), (1, 0.0), (2, -0.5), (3, 0.05), (4, 0.01), (5, 0.03), (6, 0.005)] {
+            top.offer(regression_per, regression(index, regression_per));
+        }
+        let kept: Vec<(u64, f64)> = top.items.iter().map(|item| (item.index, item.regression_per)).collect();
+        assert_eq!(kept, [(3, 0.05), (5, 0.03), (0, 0.02)]);
+
+        let mut disabled = TopRegressions::new(0);
+        disabled.offer(0.5, || panic!("built a regression that is not kept"));
+        assert!(disabled.items.is_empty());
+    }
+
+    #[test]
+    fn pairs_are_grouped_by_address_and_symbol_worst_first() {
+        let mut stats = RunStats::new(&test_support::config(serde_json::json!({})));
+        let request = |to_token: &str, to_token_addr: &str| LogContent {
+            to_token_addr: to_token_addr.to_owned(),
+            ..test_support::request("TRX", to_token, "1")
+        };
+        // Two USDT tokens share a symbol, the fake one regressing by 10%.
+        for (to_token, to_token_addr, new_amount) in [("USDT", "TUSDT", "100"), ("USDT", "TFAKE", "90"), ("SUN", "TSUN", "99")] {
+            let request = request(to_token, to_token_addr);
+            let result = |amount: &str| -> RouterResult {
+                serde_json::from_value(serde_json::json!({ "code": 0, "message": "ok", "data": [{ "amount": amount }] })).unwrap()
+            };
+            let (old, new) = (result("100"), result(new_amount));
+            let compares = CompareResult::gen_from_results(&old, &new, None);
+            let request = RequestCompare { index: 0, request_time: None, request, timing: Default::default(), compares };
+            stats.record_success(&request, &old, &new);
+        }
+        stats.record_failure(&RouterFailure::default(), &request("SUN", "TSUN"));
+
+        let by_addr: Vec<(&str, f64, u64)> = stats
+            .pairs_by_regression()
+            .iter()
+            .map(|pair| (pair.to_token_addr.as_str(), (pair.mean_amount_regression * 100.0).round(), pair.failed))
+            .collect();
+        assert_eq!(by_addr, [("TFAKE", 10.0, 0), ("TSUN", 1.0, 1), ("TUSDT", 0.0, 0)]);
+        let by_symbol: Vec<(&str, &str, f64, u64)> = stats
+            .symbol_pairs_by_regression()
+            .iter()
+            .map(|pair| (pair.to_token.as_str(), pair.to_token_addr.as_str(), (pair.mean_amount_regression * 100.0).round(), pair.compared))
+            .collect();
+        assert_eq!(by_symbol, [("USDT", "*", 5.0, 2), ("SUN", "TSUN", 1.0, 1)]);
+    }
+
+    #[test]
+    fn any_number_of_paths_is_compared_up_to_the_limit() {
+        let mut stats = RunStats::new(&test_support::config(serde_json::json!({})));
+        // `paths` paths whose pools are `v0`, `v1`... but `other` for `differing`.
+        let result = |paths: usize, differing: Option<usize>| -> RouterResult {
+            let data: Vec<serde_json::Value> = (0..paths)
+                .map(|i| {
+                    let pool = if Some(i) == differing { "other".to_owned() } else { format!("v{}", i) };
+                    serde_json::json!({ "amount": "100", "pool": [pool], "roadForAddr": ["TTRX", "TUSDT"] })
+                })
+                .collect();
+            serde_json::from_value(serde_json::json!({ "code": 0, "message": "ok", "data": data })).unwrap()
+        };
+        let mut compared = vec![];
+        for (old, new, max_compared_paths) in [
+            (result(5, None), result(4, Some(3)), None),
+            (result(6, None), result(6, None), Some(5)),
+            (result(2, None), result(3, None), None),
+        ] {
+            let compares = CompareResult::gen_from_results(&old, &new, max_compared_paths);
+            compared.push(compares.len());
+            let request = RequestCompare {
+                index: 0,
+                request_time: None,
+                request: test_support::request("TRX", "USDT", "1"),
+                timing: Default::default(),
+                compares,
+            };
+            stats.record_success(&request, &old, &new);
+        }
+        assert_eq!(compared, [4, 5, 2]);
+
+        let equality: Vec<(u64, u64)> = stats.paths.equality.iter().map(|path| (path.same, path.differ)).collect();
+        assert_eq!(equality, [(3, 0), (3, 0), (2, 0), (1, 1), (1, 0)]);
+        let path_counts: Vec<((usize, usize), u64)> = stats.paths.path_counts.iter().map(|(k, v)| (*k, *v)).collect();
+        assert_eq!(path_counts, [((2, 3), 1), ((5, 4), 1), ((6, 6), 1)]);
+        assert_eq!(stats.paths.count_mismatch(), (3, 1, 1, 1));
+        let per_path: Vec<u64> = stats.all_path_diffs.by_metric[&Metric::Amount].iter().map(Histogram::count).collect();
+        assert_eq!(per_path, [3, 3, 2, 2, 1]);
+        let same_path: Vec<u64> = stats.same_path_diffs.by_metric[&Metric::Amount].iter().map(Histogram::count).collect();
+        assert_eq!(same_path, [3, 3, 2, 1, 1]);
+    }
+
+    #[test]
+    fn error_samples_are_capped() {
+        let mut errors = ErrorStats::default();
+        let failure = RouterFailure { old: Some(RouterError::Timeout), new: None, ..Default::default() };
+        for i in 0..MAX_ERROR_SAMPLES + 2 {
+            errors.record(&failure, &i.to_string());
+        }
+        let timeouts = &errors.old["timeout"];
+        assert_eq!(timeouts.count, MAX_ERROR_SAMPLES as u64 + 2);
+        assert_eq!(timeouts.samples.len(), MAX_ERROR_SAMPLES);
+        assert_eq!(timeouts.samples[0].request, "0");
+        assert!(errors.new.is_empty());
+    }
+}
@@ -0,0 +1,36 @@
+//! Fixtures shared by the unit tests.
+
+use serde_json::json;
+
+use crate::types::{Config, LogContent};
+
+/// Config with only the required settings, `overrides` replacing or adding fields.
+pub fn config(overrides: serde_json::Value) -> Config {
+    let mut config = json!({
+        "oldUrl": "http://old/routingInV2",
+        "newUrl": "http://new/routingInV2",
+        "compareResPath": "compare",
+        "compareResDetailPath": "compare_d",
+        "useBaseTokens": "true",
+        "maxCount": 10,
+    });
+    for (key, value) in overrides.as_object().unwrap() {
+        config[key] = value.clone();
+    }
+    serde_json::from_value(config).unwrap()
+}
+
+/// `in_amount` base units of `from_token` to `to_token`, both with 6 decimals and their symbol
+/// prefixed with `T` as address, e.g. `TTRX`.
+pub fn request(from_token: &str, to_token: &str, in_amount: &str) -> LogContent {
+    LogContent {
+        from_token: from_token.to_owned(),
+        to_token: to_token.to_owned(),
+        from_token_addr: format!("T{}", from_token),
+        to_token_addr: format!("T{}", to_token),
+        in_amount: in_amount.to_owned(),
+        from_decimal: 6,
+        to_decimal: 6,
+        use_base_tokens: None,
+    }
+}
@@ -0,0 +1,117 @@
+use anyhow::format_err;
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone};
+use serde::{Deserialize, Serialize};
+
+/// Format of the timestamp leading a log line, read in the local time zone. A comma before
+/// the fraction, as logback writes it, is read as a dot: `%f` would take `123` for nanoseconds.
+const LINE_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+/// `[since, until)` window of log times, either end may be open.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TimeRange {
+    #[serde(rename = "since")]
+    pub since: Option<DateTime<FixedOffset>>,
+    #[serde(rename = "until")]
+    pub until: Option<DateTime<FixedOffset>>,
+}
+
+impl TimeRange {
+    /// Bounds are RFC 3339, `2026-10-01 12:00:00` or `2026-10-01` in local time, or a
+    /// duration before `now` such as `90s`, `30m`, `2h`, `1d` or `1w`.
+    pub fn new(since: Option<&str>, until: Option<&str>, now: DateTime<FixedOffset>) -> anyhow::Result<Self> {
+        let range = Self {
+            since: since.map(|since| parse_bound(since, now)).transpose()?,
+            until: until.map(|until| parse_bound(until, now)).transpose()?,
+        };
+        if let (Some(since), Some(until)) = (range.since, range.until) {
+            if since >= until {
+                return Err(format_err!("since {} is not before until {}", since, until));
+            }
+        }
+        Ok(range)
+    }
+
+    pub fn is_bounded(&self) -> bool {
+        self.since.is_some() || self.until.is_some()
+    }
+
+    /// Lines without a timestamp only pass an unbounded range.
+    pub fn contains(&self, time: Option<DateTime<FixedOffset>>) -> bool {
+        match time {
+            Some(time) => self.since.is_none_or(|since| since <= time) && self.until.is_none_or(|until| time < until),
+            None => !self.is_bounded(),
+        }
+    }
+}
+
+/// Timestamp leading a log line, e.g. `2026-10-01 12:00:00.123 INFO ...` or
+/// `2026-10-01T12:00:00.123Z INFO ...`.
+pub fn line_time(line: &str) -> Option<DateTime<FixedOffset>> {
+    let mut tokens = line.split_whitespace();
+    let first = tokens.next()?;
+    if let Ok(time) = DateTime::parse_from_rfc3339(first) {
+        return Some(time);
+    }
+    let stamp = format!("{} {}", first, tokens.next()?.replacen(',', ".", 1));
+    NaiveDateTime::parse_from_str(&stamp, LINE_TIME_FORMAT).ok().and_then(local)
+}
+
+fn parse_bound(bound: &str, now: DateTime<FixedOffset>) -> anyhow::Result<DateTime<FixedOffset>> {
+    let bound = bound.trim();
+    if let Some(ago) = parse_duration(bound) {
+        return now.checked_sub_signed(ago).ok_or_else(|| format_err!("invalid time bound {}", bound));
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(bound) {
+        return Ok(time);
+    }
+    let naive = NaiveDateTime::parse_from_str(bound, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .or_else(|| NaiveDate::parse_from_str(bound, "%Y-%m-%d").ok().and_then(|date| date.and_hms_opt(0, 0, 0)));
+    naive.and_then(local).ok_or_else(|| format_err!("invalid time bound {}", bound))
+}
+
+/// `None` for other text and for durations too long to represent.
+fn parse_duration(text: &str) -> Option<Duration> {
+    let unit = text.chars().last()?;
+    let count: i64 = text[..text.len() - unit.len_utf8()].parse().ok()?;
+    let unit_millis = match unit {
+        's' => 1_000,
+        'm' => 60_000,
+        'h' => 3_600_000,
+        'd' => 86_400_000,
+        'w' => 604_800_000,
+        _ => return None,
+    };
+    count.checked_mul(unit_millis).map(Duration::milliseconds)
+}
+
+fn local(naive: NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+    Local.from_local_datetime(&naive).earliest().map(DateTime::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_line_times_and_bounds() {
+        let time = line_time("2026-10-01T12:00:00.123+08:00 INFO [http-nio] request-swap-routingInV2").unwrap();
+        assert_eq!(time.to_rfc3339(), "2026-10-01T12:00:00.123+08:00");
+        for line in ["2026-10-01 12:00:00.123 INFO request", "2026-10-01 12:00:00,123 INFO request"] {
+            let time = line_time(line).unwrap();
+            assert_eq!(time.format("%Y-%m-%d %H:%M:%S%.3f").to_string(), "2026-10-01 12:00:00.123", "{}", line);
+        }
+        assert!(line_time("INFO request").is_none());
+
+        let now = DateTime::parse_from_rfc3339("2026-10-01T13:00:00+08:00").unwrap();
+        let range = TimeRange::new(Some("2h"), Some("2026-10-01T12:30:00+08:00"), now).unwrap();
+        assert!(range.contains(Some(time)));
+        assert!(!range.contains(Some(now)));
+        assert!(!range.contains(None));
+        assert!(TimeRange::new(Some("1h"), Some("2h"), now).is_err());
+        assert!(TimeRange::new(Some("yesterday"), None, now).is_err());
+        for bound in ["99999999999999w", "99999999999999d", "99999999999999h", "9223372036854775807s"] {
+            assert!(TimeRange::new(Some(bound), None, now).is_err(), "{}", bound);
+        }
+    }
+}
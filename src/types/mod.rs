@@ -1,5 +1,10 @@
+use std::str::FromStr;
+use bigdecimal::{BigDecimal, Signed, ToPrimitive, Zero};
 use serde::{Deserialize, Serialize};
 
+/// Significant digits kept for relative differences, which rarely terminate.
+const REL_DIFF_PRECISION: u64 = 32;
+
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct LogContent {
@@ -17,6 +22,18 @@ pub struct LogContent {
     pub from_decimal: u16,
     #[serde(rename = "toDecimal")]
     pub to_decimal: u16,
+    /// `useBaseTokens` of a logged router call, replayed to the new router instead of the
+    /// configured one.
+    #[serde(rename = "useBaseTokens", default, skip_serializing_if = "Option::is_none")]
+    pub use_base_tokens: Option<String>,
+}
+
+impl LogContent {
+    /// `in_amount` in whole `fromToken` units, `None` when it is not a positive number.
+    pub fn amount(&self) -> Option<f64> {
+        let amount = self.in_amount.parse::<f64>().ok()? / 10f64.powi(self.from_decimal as i32);
+        (amount > 0.0 && amount.is_finite()).then_some(amount)
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -50,78 +67,728 @@ pub struct Path {
     // pub paths:u32,
 }
 
+impl Path {
+    pub fn value(&self, metric: Metric) -> Option<&String> {
+        match metric {
+            Metric::Amount => self.amount.as_ref(),
+            Metric::Fee => self.fee.as_ref(),
+            Metric::Impact => self.impact.as_ref(),
+            Metric::InUsd => self.in_usd.as_ref(),
+            Metric::OutUsd => self.out_usd.as_ref(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Config {
     #[serde(rename = "oldUrl")]
     pub old_url: String,
     #[serde(rename = "newUrl")]
     pub new_url: String,
-    #[serde(rename = "logFilePath")]
+    /// Single input log, `-` for stdin; may be left empty when `logFilePaths` is set.
+    #[serde(rename = "logFilePath", default)]
     pub log_file_path: String,
+    /// More input logs or glob patterns such as `/var/log/tron.log*`. gzip and zstd files
+    /// are decompressed, and all inputs are read highest rotation number first, then oldest
+    /// modification time first.
+    #[serde(rename = "logFilePaths", default)]
+    pub log_file_paths: Vec<String>,
     #[serde(rename = "compareResDetailPath")]
     pub compare_res_detail_path: String,
     #[serde(rename = "compareResPath")]
     pub compare_res_path: String,
     #[serde(rename = "useBaseTokens")]
     pub use_base_tokens: String,
+    /// Requests compared at most, ignored in follow mode which runs until interrupted.
     #[serde(rename = "maxCount")]
     pub max_count: u64,
+    #[serde(rename = "maxConcurrency", default = "default_max_concurrency")]
+    pub max_concurrency: usize,
+    #[serde(rename = "simultaneousCalls", default)]
+    pub simultaneous_calls: bool,
+    /// Relative amount difference under which old and new quotes count as a tie.
+    #[serde(rename = "tieTolerance", default = "default_tie_tolerance")]
+    pub tie_tolerance: f64,
+    #[serde(rename = "diffBuckets", default)]
+    pub diff_buckets: DiffBuckets,
+    /// Only the first `maxComparedPaths` paths of each response are compared when set.
+    #[serde(rename = "maxComparedPaths", default)]
+    pub max_compared_paths: Option<usize>,
+    /// Structured summary of the whole run, written at the end and rewritten on every flush
+    /// in follow mode.
+    #[serde(rename = "jsonReportPath", default)]
+    pub json_report_path: Option<String>,
+    /// JSON Lines file with one object per compared request.
+    #[serde(rename = "jsonDetailPath", default)]
+    pub json_detail_path: Option<String>,
+    /// CSV file with one row per compared path.
+    #[serde(rename = "csvPath", default)]
+    pub csv_path: Option<String>,
+    /// Self-contained HTML report, written like `jsonReportPath`.
+    #[serde(rename = "htmlReportPath", default)]
+    pub html_report_path: Option<String>,
+    /// Number of worst regressions kept per metric for the reports.
+    #[serde(rename = "topRegressions", default = "default_top_regressions")]
+    pub top_regressions: usize,
+    /// Ranked regressions with replay curl lines, written like `jsonReportPath`.
+    #[serde(rename = "regressionReportPath", default)]
+    pub regression_report_path: Option<String>,
+    /// Markdown summary for release notes, written like `jsonReportPath`.
+    #[serde(rename = "markdownReportPath", default)]
+    pub markdown_report_path: Option<String>,
+    #[serde(rename = "thresholds", default)]
+    pub thresholds: Thresholds,
+    /// Which log entries count as the same request; repeated ones are skipped.
+    #[serde(rename = "dedup", default)]
+    pub dedup: Dedup,
+    /// Which of the deduplicated log entries are compared, at most `maxCount` of them.
+    #[serde(rename = "sampling", default)]
+    pub sampling: Sampling,
+    /// Keeps tailing `logFilePath` instead of stopping at its end, see `Follow`.
+    #[serde(rename = "follow", default)]
+    pub follow: Option<Follow>,
+    /// Progress is saved there every `checkpointEvery` processed entries so an interrupted
+    /// run can be resumed; a journal of the processed entries is kept next to it.
+    #[serde(rename = "checkpointPath", default)]
+    pub checkpoint_path: Option<String>,
+    #[serde(rename = "checkpointEvery", default = "default_checkpoint_every")]
+    pub checkpoint_every: u64,
+    /// Continue from the checkpoint instead of starting over, also set by `--resume`.
+    #[serde(rename = "resume", default)]
+    pub resume: bool,
+    /// Only entries logged at or after this time are compared: RFC 3339, `2026-10-01 12:00:00`
+    /// in local time, or a duration before now such as `30m`. Also set by `--since`.
+    #[serde(rename = "since", default)]
+    pub since: Option<String>,
+    /// Only entries logged before this time are compared, same formats as `since`. Also set
+    /// by `--until`.
+    #[serde(rename = "until", default)]
+    pub until: Option<String>,
+    /// How routing requests are found in and decoded from log lines, see `LogFormat`.
+    #[serde(rename = "logFormat", default)]
+    pub log_format: LogFormat,
+}
+
+/// Format of the log lines, e.g. `{"format": "nginx", "marker": "/swap/routingInV2?"}`.
+/// Lines without the `marker` of their format are skipped.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "format")]
+pub enum LogFormat {
+    /// `... request-swap-routingInV2 --GRAFANA_INFO--{"logContent": "<request JSON>"}`, the
+    /// request JSON following `separator` as a string under `field`.
+    #[serde(rename = "grafana")]
+    Grafana {
+        #[serde(rename = "marker", default = "default_grafana_marker")]
+        marker: String,
+        #[serde(rename = "separator", default = "default_grafana_separator")]
+        separator: String,
+        #[serde(rename = "field", default = "default_grafana_field")]
+        field: String,
+    },
+    /// One JSON object per line holding the request, under `field` when set, either as an
+    /// object or a JSON string. `timeField` holds an RFC 3339 timestamp.
+    #[serde(rename = "jsonLines")]
+    JsonLines {
+        #[serde(rename = "marker", default)]
+        marker: Option<String>,
+        #[serde(rename = "field", default)]
+        field: Option<String>,
+        #[serde(rename = "timeField", default = "default_json_time_field")]
+        time_field: String,
+    },
+    /// nginx access logs in the combined format, the request read from the query string of
+    /// the request line.
+    #[serde(rename = "nginx")]
+    Nginx {
+        #[serde(rename = "marker", default = "default_query_marker")]
+        marker: String,
+    },
+    /// Any line holding a router call URL such as `.../routingInV2?fromToken=...`, the
+    /// request read from the query string following `marker`.
+    #[serde(rename = "queryString")]
+    QueryString {
+        #[serde(rename = "marker", default = "default_query_marker")]
+        marker: String,
+    },
+    /// `logcli query --output jsonl` exports, `{"line": "...", "timestamp": "..."}`, each
+    /// `line` in the `inner` format.
+    #[serde(rename = "loki")]
+    Loki {
+        #[serde(rename = "inner", default)]
+        inner: Box<LogFormat>,
+    },
+}
+
+impl Default for LogFormat {
+    fn default() -> Self {
+        LogFormat::Grafana {
+            marker: default_grafana_marker(),
+            separator: default_grafana_separator(),
+            field: default_grafana_field(),
+        }
+    }
+}
+
+/// Follow mode: `logFilePath` is tailed like `tail -F`, surviving rotation and truncation,
+/// and the reports are rewritten every `flushSecs` until the process is interrupted. Every
+/// report covers one flush period, which bounds what a long running canary keeps in memory.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Follow {
+    /// How long to wait before checking the log again once its end is reached.
+    #[serde(rename = "pollMillis", default = "default_follow_poll_millis")]
+    pub poll_millis: u64,
+    #[serde(rename = "flushSecs", default = "default_follow_flush_secs")]
+    pub flush_secs: u64,
+    /// Read the existing content first instead of only the lines appended from now on.
+    #[serde(rename = "fromStart", default)]
+    pub from_start: bool,
+}
+
+/// Sampling of the deduplicated log entries. Every mode but `first` reads the whole log
+/// before comparing and keeps the sampled entries in log order; `seed` makes the sample
+/// reproducible and a random one is logged when it is not set.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode")]
+pub enum Sampling {
+    /// The first `maxCount` entries of the log.
+    #[default]
+    #[serde(rename = "first")]
+    First,
+    /// `maxCount` entries drawn uniformly over the whole log.
+    #[serde(rename = "reservoir")]
+    Reservoir {
+        #[serde(rename = "seed", default)]
+        seed: Option<u64>,
+    },
+    /// Up to `perPair` entries drawn uniformly for each `fromToken`/`toToken` pair.
+    #[serde(rename = "stratifiedPair")]
+    StratifiedPair {
+        #[serde(rename = "perPair")]
+        per_pair: usize,
+        #[serde(rename = "seed", default)]
+        seed: Option<u64>,
+    },
+    /// Up to `count` entries drawn uniformly for each amount range, see `AmountQuota`.
+    #[serde(rename = "amountQuotas")]
+    AmountQuotas {
+        #[serde(rename = "quotas")]
+        quotas: Vec<AmountQuota>,
+        #[serde(rename = "seed", default)]
+        seed: Option<u64>,
+    },
+}
+
+impl Sampling {
+    pub fn seed(&self) -> Option<u64> {
+        match self {
+            Sampling::First => None,
+            Sampling::Reservoir { seed } | Sampling::StratifiedPair { seed, .. } | Sampling::AmountQuotas { seed, .. } => {
+                *seed
+            }
+        }
+    }
+}
+
+/// Amounts are in whole `fromToken` units. A range covers amounts below its `maxAmount`
+/// and at or above the previous one's, ranges are sorted by `maxAmount` and the last may
+/// leave it unset to be open above. Entries outside every range are skipped.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AmountQuota {
+    #[serde(rename = "maxAmount", default)]
+    pub max_amount: Option<f64>,
+    #[serde(rename = "count")]
+    pub count: usize,
+}
+
+/// Dedup policy applied to decoded log entries, e.g. `{"mode": "pairSamples", "perPair": 5}`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode")]
+pub enum Dedup {
+    /// Every log entry is compared.
+    #[serde(rename = "none")]
+    None,
+    /// Only the first entry of each `fromToken`/`toToken` pair.
+    #[default]
+    #[serde(rename = "pair")]
+    Pair,
+    /// The first entry of each pair and order of magnitude of the amount in whole tokens.
+    #[serde(rename = "pairAmountBucket")]
+    PairAmountBucket,
+    /// The first entry of each distinct request.
+    #[serde(rename = "request")]
+    Request,
+    /// Up to `perPair` entries of each pair.
+    #[serde(rename = "pairSamples")]
+    PairSamples {
+        #[serde(rename = "perPair")]
+        per_pair: usize,
+    },
+}
+
+/// Limits a run must stay within to pass; unset limits are not checked. Rates and
+/// regressions are ratios, e.g. `0.01` for 1%.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Thresholds {
+    /// Share of requests where either router failed.
+    #[serde(rename = "maxFailedRate", default)]
+    pub max_failed_rate: Option<f64>,
+    /// Share of first paths where the old router quoted more.
+    #[serde(rename = "maxOldBetterRate", default)]
+    pub max_old_better_rate: Option<f64>,
+    /// Largest tolerated amount regression of a single path.
+    #[serde(rename = "maxAmountRegression", default)]
+    pub max_amount_regression: Option<f64>,
+    /// Share of first paths with the same pool and road.
+    #[serde(rename = "minSamePathRate", default)]
+    pub min_same_path_rate: Option<f64>,
+}
+
+/// Upper-exclusive edges (relative diff ratios, ascending) of the histogram of each metric.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DiffBuckets {
+    #[serde(rename = "amount", default = "default_diff_bucket_edges")]
+    pub amount: Vec<f64>,
+    #[serde(rename = "fee", default = "default_diff_bucket_edges")]
+    pub fee: Vec<f64>,
+    #[serde(rename = "impact", default = "default_diff_bucket_edges")]
+    pub impact: Vec<f64>,
+    #[serde(rename = "inUsd", default = "default_diff_bucket_edges")]
+    pub in_usd: Vec<f64>,
+    #[serde(rename = "outUsd", default = "default_diff_bucket_edges")]
+    pub out_usd: Vec<f64>,
+}
+
+impl DiffBuckets {
+    pub fn edges(&self, metric: Metric) -> &[f64] {
+        match metric {
+            Metric::Amount => &self.amount,
+            Metric::Fee => &self.fee,
+            Metric::Impact => &self.impact,
+            Metric::InUsd => &self.in_usd,
+            Metric::OutUsd => &self.out_usd,
+        }
+    }
+}
+
+impl Default for DiffBuckets {
+    fn default() -> Self {
+        Self {
+            amount: default_diff_bucket_edges(),
+            fee: default_diff_bucket_edges(),
+            impact: default_diff_bucket_edges(),
+            in_usd: default_diff_bucket_edges(),
+            out_usd: default_diff_bucket_edges(),
+        }
+    }
+}
+
+fn default_diff_bucket_edges() -> Vec<f64> {
+    vec![0.0001, 0.001, 0.01, 0.02, 0.05, 0.1]
+}
+
+fn default_max_concurrency() -> usize {
+    1
+}
+
+fn default_tie_tolerance() -> f64 {
+    0.0001
+}
+
+fn default_top_regressions() -> usize {
+    10
+}
+
+fn default_checkpoint_every() -> u64 {
+    1000
+}
+
+fn default_follow_poll_millis() -> u64 {
+    500
+}
+
+fn default_follow_flush_secs() -> u64 {
+    60
+}
+
+fn default_grafana_marker() -> String {
+    "request-swap-routingInV2".to_owned()
+}
+
+fn default_grafana_separator() -> String {
+    "--GRAFANA_INFO--".to_owned()
+}
+
+fn default_grafana_field() -> String {
+    "logContent".to_owned()
+}
+
+fn default_json_time_field() -> String {
+    "time".to_owned()
+}
+
+fn default_query_marker() -> String {
+    "routingInV2?".to_owned()
+}
+
+/// Wall-clock start (unix millis), cost (millis, retries included) and retry count of
+/// the old and new router calls made for one log entry.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RouterTiming {
+    #[serde(rename = "oldStart")]
+    pub old_start: i64,
+    #[serde(rename = "oldCost")]
+    pub old_cost: i64,
+    #[serde(rename = "newStart")]
+    pub new_start: i64,
+    #[serde(rename = "newCost")]
+    pub new_cost: i64,
+    #[serde(rename = "oldRetries")]
+    pub old_retries: u32,
+    #[serde(rename = "newRetries")]
+    pub new_retries: u32,
+}
+
+impl RouterTiming {
+    /// Gap between the two requests leaving; with `simultaneousCalls` this should be ~0.
+    pub fn start_skew(&self) -> i64 {
+        (self.new_start - self.old_start).abs()
+    }
+}
+
+/// How one field of a path compared between the old and new router responses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum FieldOutcome {
+    #[serde(rename = "equal")]
+    Equal,
+    #[serde(rename = "differs")]
+    Differs,
+    #[serde(rename = "missingInOld")]
+    MissingInOld,
+    #[serde(rename = "missingInNew")]
+    MissingInNew,
+    #[serde(rename = "missingInBoth")]
+    MissingInBoth,
+    #[serde(rename = "unparsable")]
+    Unparsable,
+}
+
+impl FieldOutcome {
+    pub const ALL: [FieldOutcome; 6] = [
+        FieldOutcome::Equal,
+        FieldOutcome::Differs,
+        FieldOutcome::MissingInOld,
+        FieldOutcome::MissingInNew,
+        FieldOutcome::MissingInBoth,
+        FieldOutcome::Unparsable,
+    ];
+
+    fn of_options<T>(old: &Option<T>, new: &Option<T>) -> Option<Self> {
+        match (old, new) {
+            (Some(_), Some(_)) => None,
+            (None, Some(_)) => Some(FieldOutcome::MissingInOld),
+            (Some(_), None) => Some(FieldOutcome::MissingInNew),
+            (None, None) => Some(FieldOutcome::MissingInBoth),
+        }
+    }
+
+    fn of_eq<T: PartialEq>(old: &Option<T>, new: &Option<T>) -> Self {
+        Self::of_options(old, new).unwrap_or(if old == new {
+            FieldOutcome::Equal
+        } else {
+            FieldOutcome::Differs
+        })
+    }
+}
+
+impl std::fmt::Display for FieldOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            FieldOutcome::Equal => "equal",
+            FieldOutcome::Differs => "differs",
+            FieldOutcome::MissingInOld => "missingInOld",
+            FieldOutcome::MissingInNew => "missingInNew",
+            FieldOutcome::MissingInBoth => "missingInBoth",
+            FieldOutcome::Unparsable => "unparsable",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Decimal fields of a path whose differences are measured.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Metric {
+    #[serde(rename = "amount")]
+    Amount,
+    #[serde(rename = "fee")]
+    Fee,
+    #[serde(rename = "impact")]
+    Impact,
+    #[serde(rename = "inUsd")]
+    InUsd,
+    #[serde(rename = "outUsd")]
+    OutUsd,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 5] = [Metric::Amount, Metric::Fee, Metric::Impact, Metric::InUsd, Metric::OutUsd];
+
+    /// Metrics whose regressions are ranked; `inUsd` only echoes the request.
+    pub const REGRESSION: [Metric; 4] = [Metric::Amount, Metric::OutUsd, Metric::Fee, Metric::Impact];
+
+    /// Whether a larger value from the new router is an improvement.
+    pub fn higher_is_better(&self) -> bool {
+        matches!(self, Metric::Amount | Metric::InUsd | Metric::OutUsd)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::Amount => "Amount",
+            Metric::Fee => "Fee",
+            Metric::Impact => "Impact",
+            Metric::InUsd => "InUsd",
+            Metric::OutUsd => "OutUsd",
+        }
+    }
+}
+
+/// Which router gave the better quote for a path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Side {
+    #[serde(rename = "old")]
+    Old,
+    #[serde(rename = "new")]
+    New,
+    #[serde(rename = "tie")]
+    Tie,
 }
 
+/// Exact difference of one decimal field between the old and new router responses.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DecimalDiff {
+    /// `|old - new|`, exact.
+    #[serde(rename = "abs")]
+    pub abs: BigDecimal,
+    /// `|old - new| / |old|` rounded to `REL_DIFF_PRECISION` significant digits, `None` when
+    /// `old` is zero and `new` is not.
+    #[serde(rename = "rel")]
+    pub rel: Option<BigDecimal>,
+    /// `new - old`, exact; positive when the new router returned the larger value.
+    #[serde(rename = "delta")]
+    pub delta: BigDecimal,
+    /// `(new - old) / |old|`, signed counterpart of `rel`.
+    #[serde(rename = "deltaRel")]
+    pub delta_rel: Option<BigDecimal>,
+}
+
+impl DecimalDiff {
+    /// `None` when either side is not a decimal number.
+    pub fn from_strs(old: &str, new: &str) -> Option<Self> {
+        let old = BigDecimal::from_str(old.trim()).ok()?;
+        let new = BigDecimal::from_str(new.trim()).ok()?;
+        let delta = &new - &old;
+        let delta_rel = if delta.is_zero() {
+            Some(BigDecimal::zero())
+        } else if old.is_zero() {
+            None
+        } else {
+            Some((&delta / old.abs()).with_prec(REL_DIFF_PRECISION).normalized())
+        };
+        Some(Self {
+            abs: delta.abs(),
+            rel: delta_rel.as_ref().map(|rel| rel.abs()),
+            delta,
+            delta_rel,
+        })
+    }
+
+    /// Relative difference as a ratio for bucketing, infinite when it is undefined.
+    pub fn rel_f64(&self) -> f64 {
+        self.rel.as_ref().and_then(|rel| rel.to_f64()).unwrap_or(f64::INFINITY)
+    }
+
+    /// Signed relative difference, `±inf` when it is undefined.
+    pub fn delta_rel_f64(&self) -> f64 {
+        let rel = self.rel_f64();
+        if self.delta.is_negative() { -rel } else { rel }
+    }
+
+    /// Which side returned the larger value, values within `tolerance` (relative) tie.
+    pub fn larger_side(&self, tolerance: f64) -> Side {
+        if self.rel_f64() <= tolerance {
+            Side::Tie
+        } else if self.delta.is_positive() {
+            Side::New
+        } else {
+            Side::Old
+        }
+    }
+
+    fn compare(old: &Option<String>, new: &Option<String>) -> (FieldOutcome, Option<Self>) {
+        if let Some(outcome) = FieldOutcome::of_options(old, new) {
+            return (outcome, None);
+        }
+        match Self::from_strs(old.as_ref().unwrap(), new.as_ref().unwrap()) {
+            Some(diff) if diff.abs.is_zero() => (FieldOutcome::Equal, Some(diff)),
+            Some(diff) => (FieldOutcome::Differs, Some(diff)),
+            None => (FieldOutcome::Unparsable, None),
+        }
+    }
+}
+
+/// Outcome of every compared field of a path.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FieldOutcomes {
+    #[serde(rename = "amount")]
+    pub amount: FieldOutcome,
+    #[serde(rename = "fee")]
+    pub fee: FieldOutcome,
+    #[serde(rename = "impact")]
+    pub impact: FieldOutcome,
+    #[serde(rename = "inUsd")]
+    pub in_usd: FieldOutcome,
+    #[serde(rename = "outUsd")]
+    pub out_usd: FieldOutcome,
+    #[serde(rename = "pool")]
+    pub pool: FieldOutcome,
+    #[serde(rename = "roadForAddr")]
+    pub road_for_addr: FieldOutcome,
+}
+
+impl FieldOutcomes {
+    pub fn iter(&self) -> impl Iterator<Item=(&'static str, FieldOutcome)> {
+        [
+            ("amount", self.amount),
+            ("fee", self.fee),
+            ("impact", self.impact),
+            ("inUsd", self.in_usd),
+            ("outUsd", self.out_usd),
+            ("pool", self.pool),
+            ("roadForAddr", self.road_for_addr),
+        ].into_iter()
+    }
+}
+
+/// Comparison of every compared path of one log entry.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RequestCompare {
+    #[serde(rename = "index")]
+    pub index: u64,
+    /// When the request was logged, `None` when the line has no timestamp.
+    #[serde(rename = "requestTime", default)]
+    pub request_time: Option<chrono::DateTime<chrono::FixedOffset>>,
+    #[serde(rename = "request")]
+    pub request: LogContent,
+    #[serde(rename = "timing")]
+    pub timing: RouterTiming,
+    #[serde(rename = "compares")]
+    pub compares: Vec<CompareResult>,
+}
+
+/// Comparison of one path index. The `diff*Per` ratios and exact diffs are `None` when the
+/// field could not be compared, see `fieldOutcomes` for the reason.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CompareResult {
     #[serde(rename = "diffFeePer")]
-    pub diff_fee_per: f64,
+    pub diff_fee_per: Option<f64>,
     #[serde(rename = "diffAmountPer")]
-    pub diff_amount_per: f64,
+    pub diff_amount_per: Option<f64>,
     #[serde(rename = "diffImpactPer")]
-    pub diff_impact_per: f64,
+    pub diff_impact_per: Option<f64>,
     #[serde(rename = "diffInusdPer")]
-    pub diff_inusd_per: f64,
+    pub diff_inusd_per: Option<f64>,
     #[serde(rename = "diffoutusdPer")]
-    pub diff_outusd_per: f64,
+    pub diff_outusd_per: Option<f64>,
+    #[serde(rename = "diffFee")]
+    pub diff_fee: Option<DecimalDiff>,
+    #[serde(rename = "diffAmount")]
+    pub diff_amount: Option<DecimalDiff>,
+    #[serde(rename = "diffImpact")]
+    pub diff_impact: Option<DecimalDiff>,
+    #[serde(rename = "diffInusd")]
+    pub diff_inusd: Option<DecimalDiff>,
+    #[serde(rename = "diffOutusd")]
+    pub diff_outusd: Option<DecimalDiff>,
     #[serde(rename = "poolEq")]
     pub pool_eq: bool,
     #[serde(rename = "roadForAddrEq")]
     pub road_addr_eq: bool,
+    #[serde(rename = "fieldOutcomes")]
+    pub field_outcomes: FieldOutcomes,
 }
 
 
 impl CompareResult {
-    pub fn gen_from_paths(old: &Path, new: &Path) -> Option<Self> {
-        if old.amount.is_none() || new.amount.is_none() {
-            return None;
+    pub fn diff(&self, metric: Metric) -> Option<&DecimalDiff> {
+        match metric {
+            Metric::Amount => self.diff_amount.as_ref(),
+            Metric::Fee => self.diff_fee.as_ref(),
+            Metric::Impact => self.diff_impact.as_ref(),
+            Metric::InUsd => self.diff_inusd.as_ref(),
+            Metric::OutUsd => self.diff_outusd.as_ref(),
         }
-        let diff_amount_per = clac_string_per(old.amount.clone().unwrap(), new.amount.clone().unwrap());
-        let diff_fee_per = clac_string_per(old.fee.clone().unwrap(), new.fee.clone().unwrap());
-        let diff_impact_per = clac_string_per(old.impact.clone().unwrap(), new.impact.clone().unwrap());
-        let diff_inusd_per = clac_string_per(old.in_usd.clone().unwrap(), new.in_usd.clone().unwrap());
-        let diff_outusd_per = clac_string_per(old.out_usd.clone().unwrap(), new.out_usd.clone().unwrap());
-        let pool_eq = old.pool.clone().unwrap().eq(&new.pool.clone().unwrap());
-        let road_addr_eq = old.road_for_addr.clone().unwrap().eq(&new.road_for_addr.clone().unwrap());
-        Some(
-            Self {
-                diff_fee_per,
-                diff_amount_per,
-                diff_impact_per,
-                diff_inusd_per,
-                diff_outusd_per,
-                pool_eq,
-                road_addr_eq,
-            }
-        )
     }
-}
 
+    pub fn diff_per(&self, metric: Metric) -> Option<f64> {
+        match metric {
+            Metric::Amount => self.diff_amount_per,
+            Metric::Fee => self.diff_fee_per,
+            Metric::Impact => self.diff_impact_per,
+            Metric::InUsd => self.diff_inusd_per,
+            Metric::OutUsd => self.diff_outusd_per,
+        }
+    }
 
-fn clac_string_per(a: String, b: String) -> f64 {
-    let a_f = a.parse::<f64>().expect("parse to f6");
-    let b_f = b.parse::<f64>().expect("parse to f6");
-    clac_per(a_f, b_f)
-}
+    /// The better quote is the one returning more of `toToken`; `None` when amounts could
+    /// not be compared.
+    pub fn better_side(&self, tolerance: f64) -> Option<Side> {
+        self.diff_amount.as_ref().map(|diff| diff.larger_side(tolerance))
+    }
 
-fn clac_per(a: f64, b: f64) -> f64 {
-    ((a - b) / a).abs()
+    /// Compares the paths both routers returned at the same index, at most
+    /// `max_compared_paths` of them.
+    pub fn gen_from_results(old: &RouterResult, new: &RouterResult, max_compared_paths: Option<usize>) -> Vec<Self> {
+        let old_paths = old.data.as_deref().unwrap_or_default();
+        let new_paths = new.data.as_deref().unwrap_or_default();
+        old_paths
+            .iter()
+            .zip(new_paths)
+            .take(max_compared_paths.unwrap_or(usize::MAX))
+            .map(|(old_path, new_path)| Self::gen_from_paths(old_path, new_path))
+            .collect()
+    }
+
+    pub fn gen_from_paths(old: &Path, new: &Path) -> Self {
+        let (amount, diff_amount) = DecimalDiff::compare(&old.amount, &new.amount);
+        let (fee, diff_fee) = DecimalDiff::compare(&old.fee, &new.fee);
+        let (impact, diff_impact) = DecimalDiff::compare(&old.impact, &new.impact);
+        let (in_usd, diff_inusd) = DecimalDiff::compare(&old.in_usd, &new.in_usd);
+        let (out_usd, diff_outusd) = DecimalDiff::compare(&old.out_usd, &new.out_usd);
+        let pool = FieldOutcome::of_eq(&old.pool, &new.pool);
+        let road_for_addr = FieldOutcome::of_eq(&old.road_for_addr, &new.road_for_addr);
+        Self {
+            diff_fee_per: diff_fee.as_ref().map(DecimalDiff::rel_f64),
+            diff_amount_per: diff_amount.as_ref().map(DecimalDiff::rel_f64),
+            diff_impact_per: diff_impact.as_ref().map(DecimalDiff::rel_f64),
+            diff_inusd_per: diff_inusd.as_ref().map(DecimalDiff::rel_f64),
+            diff_outusd_per: diff_outusd.as_ref().map(DecimalDiff::rel_f64),
+            diff_fee,
+            diff_amount,
+            diff_impact,
+            diff_inusd,
+            diff_outusd,
+            pool_eq: pool == FieldOutcome::Equal,
+            road_addr_eq: road_for_addr == FieldOutcome::Equal,
+            field_outcomes: FieldOutcomes {
+                amount,
+                fee,
+                impact,
+                in_usd,
+                out_usd,
+                pool,
+                road_for_addr,
+            },
+        }
+    }
 }
 
 impl Config {
@@ -130,4 +797,66 @@ impl Config {
             std::fs::read_to_string(path).expect("Unable to find the specified config file");
         serde_json::from_str(&content).expect("Invalid configuration file provided")
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decimal_diff_is_exact() {
+        let diff = DecimalDiff::from_strs("1000000000.000000000000000001", "1000000000.000000000000000002").unwrap();
+        assert_eq!(diff.abs, BigDecimal::from_str("0.000000000000000001").unwrap());
+        assert!(!diff.rel.unwrap().is_zero());
+
+        let diff = DecimalDiff::from_strs("0", "0").unwrap();
+        assert_eq!(diff.rel_f64(), 0.0);
+        let diff = DecimalDiff::from_strs("0", "1").unwrap();
+        assert!(diff.rel.is_none());
+        assert_eq!(diff.rel_f64(), f64::INFINITY);
+        assert!(DecimalDiff::from_strs("1.2.3", "1").is_none());
+
+        let diff = DecimalDiff::from_strs("200", "199").unwrap();
+        assert_eq!(diff.delta, BigDecimal::from(-1));
+        assert_eq!(diff.delta_rel_f64(), -0.005);
+        assert_eq!(diff.larger_side(0.0001), Side::Old);
+        assert_eq!(diff.larger_side(0.01), Side::Tie);
+        assert_eq!(DecimalDiff::from_strs("0", "1").unwrap().larger_side(0.01), Side::New);
+    }
+
+    #[test]
+    fn partial_paths_do_not_panic() {
+        let old = Path {
+            amount: Some("10".to_owned()),
+            fee: Some("0.3".to_owned()),
+            impact: Some("abc".to_owned()),
+            in_usd: None,
+            out_usd: Some("1".to_owned()),
+            pool: Some(vec!["v2".to_owned()]),
+            road_for_addr: None,
+            road_for_name: None,
+        };
+        let new = Path {
+            amount: Some("10.0".to_owned()),
+            fee: Some("0.4".to_owned()),
+            impact: Some("0.1".to_owned()),
+            in_usd: Some("1".to_owned()),
+            out_usd: None,
+            pool: Some(vec!["v2".to_owned()]),
+            road_for_addr: None,
+            road_for_name: None,
+        };
+        let res = CompareResult::gen_from_paths(&old, &new);
+        let outcomes = &res.field_outcomes;
+        assert_eq!(outcomes.amount, FieldOutcome::Equal);
+        assert_eq!(outcomes.fee, FieldOutcome::Differs);
+        assert_eq!(outcomes.impact, FieldOutcome::Unparsable);
+        assert_eq!(outcomes.in_usd, FieldOutcome::MissingInOld);
+        assert_eq!(outcomes.out_usd, FieldOutcome::MissingInNew);
+        assert_eq!(outcomes.pool, FieldOutcome::Equal);
+        assert_eq!(outcomes.road_for_addr, FieldOutcome::MissingInBoth);
+        assert_eq!(res.diff_amount_per, Some(0.0));
+        assert!(res.diff_impact_per.is_none());
+        assert!(res.pool_eq && !res.road_addr_eq);
+    }
+}